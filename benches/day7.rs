@@ -0,0 +1,21 @@
+#![feature(test)]
+extern crate test;
+
+use aoc2019::{day7, util};
+use test::Bencher;
+
+#[bench]
+fn day7part1(b: &mut Bencher) {
+    let input = util::read_input_file("day7.txt");
+    let ram = day7::read_input(&input[..]);
+
+    b.iter(|| day7::part1(&ram));
+}
+
+#[bench]
+fn day7part2(b: &mut Bencher) {
+    let input = util::read_input_file("day7.txt");
+    let ram = day7::read_input(&input[..]);
+
+    b.iter(|| day7::part2(&ram));
+}
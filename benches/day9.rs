@@ -0,0 +1,21 @@
+#![feature(test)]
+extern crate test;
+
+use aoc2019::{day9, util};
+use test::Bencher;
+
+#[bench]
+fn day9part1(b: &mut Bencher) {
+    let input = util::read_input_file("day9.txt");
+    let ram = day9::read_input(&input[..]);
+
+    b.iter(|| day9::part1(&ram));
+}
+
+#[bench]
+fn day9part2(b: &mut Bencher) {
+    let input = util::read_input_file("day9.txt");
+    let ram = day9::read_input(&input[..]);
+
+    b.iter(|| day9::part2(&ram));
+}
@@ -0,0 +1,83 @@
+#![feature(test)]
+extern crate test;
+
+use aoc2019::intcode::compile::Compiled;
+use aoc2019::intcode::{read_input, Computer};
+use aoc2019::util;
+use test::Bencher;
+
+fn day9() -> Vec<i64> {
+    read_input(&util::read_input_file("day9.txt")[..])
+}
+
+#[bench]
+fn clone_program(b: &mut Bencher) {
+    let program = day9();
+    b.iter(|| Computer::new(program.clone()));
+}
+
+#[bench]
+fn fork(b: &mut Bencher) {
+    let computer = Computer::new(day9());
+    b.iter(|| computer.fork());
+}
+
+#[bench]
+fn clone_and_patch(b: &mut Bencher) {
+    let program = day9();
+    b.iter(|| {
+        let mut computer = Computer::new(program.clone());
+        computer.poke(1, 12);
+        computer
+    });
+}
+
+#[bench]
+fn fork_and_patch(b: &mut Bencher) {
+    let computer = Computer::new(day9());
+    b.iter(|| {
+        let mut child = computer.fork();
+        child.poke(1, 12);
+        child
+    });
+}
+
+/// Counts the pages a thousand patched forks still share with their parent, each having copied
+/// only the page it wrote to.
+#[bench]
+fn fork_memory(b: &mut Bencher) {
+    let computer = Computer::new(day9());
+    let children: Vec<_> = (0..1000)
+        .map(|i| {
+            let mut child = computer.fork();
+            child.poke(1, i);
+            child
+        })
+        .collect();
+
+    let shared = || -> usize { children.iter().map(|c| c.memory().shared_pages()).sum() };
+    let pages = computer.memory().pages();
+    assert_eq!(children.len() * (pages - 1), shared());
+
+    b.iter(shared);
+}
+
+#[bench]
+fn interpret_boost(b: &mut Bencher) {
+    let program = day9();
+    b.iter(|| {
+        let mut computer = Computer::new(program.clone());
+        computer.push_input(2);
+        computer.run_to_halt().unwrap()
+    });
+}
+
+#[bench]
+fn compiled_boost(b: &mut Bencher) {
+    let compiled = Compiled::new(&day9());
+    b.iter(|| {
+        let mut computer = compiled.computer();
+        computer.push_input(2);
+        computer.run_to_halt().unwrap()
+    });
+}
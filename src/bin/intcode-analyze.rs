@@ -0,0 +1,34 @@
+use std::env;
+use std::fs;
+use std::process;
+
+use aoc2019::intcode::{self, analyze::Report};
+
+fn main() {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let (path, dot) = match args.as_slice() {
+        [path] => (path, false),
+        [flag, path] if flag == "--dot" => (path, true),
+        _ => {
+            eprintln!("usage: intcode-analyze [--dot] <program>");
+            process::exit(2);
+        }
+    };
+
+    let raw = fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("failed to read {}: {}", path, e);
+        process::exit(1);
+    });
+
+    let program = intcode::parse_program(&raw).unwrap_or_else(|e| {
+        eprintln!("failed to parse {}: {}", path, e);
+        process::exit(1);
+    });
+
+    let report = Report::new(&program);
+    if dot {
+        print!("{}", report.to_dot(&program));
+    } else {
+        println!("{}", report.to_json());
+    }
+}
@@ -0,0 +1,298 @@
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+use aoc2019::intcode::debug::{Access, Debugger, StopReason};
+use aoc2019::intcode::{self, Computer};
+
+const HELP: &str = "\
+commands:
+  s, step [n]               execute n instructions (default 1)
+  c, continue               run until a breakpoint, watchpoint, input request or halt
+  rs, rstep [n]             undo n instructions (default 1)
+  rc, rcontinue             run backwards to a breakpoint or the start of the history
+  lw, lastwrite <addr>      run backwards to the instruction that last wrote addr
+  lo, lastout               run backwards to the instruction that produced the last output
+  b, break <addr>           set a breakpoint
+  d, delete <addr>          delete a breakpoint
+  w, watch <addr> [r|w|rw]  stop when an instruction accesses addr (default rw)
+  unwatch <addr>            delete a watchpoint
+  info                      list breakpoints and watchpoints
+  r, regs                   show inst_ptr and relative_base
+  x, mem <addr> [len]       dump len memory cells (default 8)
+  l, list [addr] [n]        disassemble n instructions (default 10) from addr
+  p, poke <addr> <value>    write a value to memory
+  i, input <value>...       queue input values
+  o, outputs                show every output so far
+  h, help                   show this message
+  q, quit                   exit";
+
+/// The most memory cells a single `mem` command will dump.
+const MAX_DUMP: usize = 4096;
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: intcode-debug <program>");
+            process::exit(2);
+        }
+    };
+
+    let raw = fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("failed to read {}: {}", path, e);
+        process::exit(1);
+    });
+
+    let program = intcode::parse_program(&raw).unwrap_or_else(|e| {
+        eprintln!("failed to parse {}: {}", path, e);
+        process::exit(1);
+    });
+
+    let mut debugger = Debugger::new(Computer::new(program));
+    print_current(&debugger);
+
+    let stdin = io::stdin();
+    loop {
+        print!("(idb) ");
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+
+        let args: Vec<_> = line.split_whitespace().collect();
+        if args.is_empty() {
+            continue;
+        }
+
+        match run_command(&mut debugger, &args) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(msg) => println!("error: {}", msg),
+        }
+    }
+}
+
+fn run_command(debugger: &mut Debugger, args: &[&str]) -> Result<bool, String> {
+    match args[0] {
+        "s" | "step" => {
+            let n = arg_or(args, 1, 1)?;
+            let seen = debugger.outputs().len();
+
+            let mut reason = StopReason::Stepped;
+            for _ in 0..n {
+                reason = debugger.step();
+                if reason != StopReason::Stepped {
+                    break;
+                }
+            }
+
+            report(debugger, seen, reason);
+        }
+        "c" | "continue" => {
+            let seen = debugger.outputs().len();
+            let reason = debugger.cont();
+            report(debugger, seen, reason);
+        }
+        "rs" | "rstep" => {
+            let n = arg_or(args, 1, 1)?;
+            let undone = (0..n)
+                .take_while(|_| debugger.step_back().is_some())
+                .count();
+            if undone < n {
+                println!("at the start of the history");
+            }
+            print_current(debugger);
+        }
+        "rc" | "rcontinue" => {
+            match debugger.reverse_cont() {
+                Some(addr) => println!("breakpoint at {}", addr),
+                None => println!("at the start of the history"),
+            }
+            print_current(debugger);
+        }
+        "lw" | "lastwrite" => {
+            let addr = arg(args, 1)?;
+            match debugger.run_back_to_write(addr) {
+                Some(entry) => {
+                    let write = entry.write.unwrap();
+                    println!("wrote {} over {} at {}", write.new, write.old, addr);
+                }
+                None => println!("no recorded write to {}", addr),
+            }
+            print_current(debugger);
+        }
+        "lo" | "lastout" => {
+            match debugger.rewind_to_output() {
+                Some(entry) => println!("output {}", entry.output.unwrap()),
+                None => println!("no recorded output"),
+            }
+            print_current(debugger);
+        }
+        "b" | "break" => {
+            let addr = arg(args, 1)?;
+            debugger.add_breakpoint(addr);
+            println!("breakpoint at {}", addr);
+        }
+        "d" | "delete" => {
+            let addr = arg(args, 1)?;
+            if !debugger.remove_breakpoint(addr) {
+                return Err(format!("no breakpoint at {}", addr));
+            }
+        }
+        "w" | "watch" => {
+            let addr = arg(args, 1)?;
+            let access = match args.get(2) {
+                None | Some(&"rw") => Access::ReadWrite,
+                Some(&"r") => Access::Read,
+                Some(&"w") => Access::Write,
+                Some(other) => return Err(format!("unknown access {:?}", other)),
+            };
+            debugger.watch(addr, access);
+            println!("watching {} ({:?})", addr, access);
+        }
+        "unwatch" => {
+            let addr = arg(args, 1)?;
+            if !debugger.unwatch(addr) {
+                return Err(format!("no watchpoint at {}", addr));
+            }
+        }
+        "info" => {
+            for addr in debugger.breakpoints() {
+                println!("breakpoint {}", addr);
+            }
+            for (addr, access) in debugger.watchpoints() {
+                println!("watchpoint {} ({:?})", addr, access);
+            }
+        }
+        "r" | "regs" => {
+            let computer = debugger.computer();
+            println!("inst_ptr      {}", computer.inst_ptr());
+            println!("relative_base {}", computer.relative_base());
+            println!("memory_len    {}", computer.memory_len());
+        }
+        "x" | "mem" => {
+            let addr: usize = arg(args, 1)?;
+            let len: usize = arg_or(args, 2, 8)?;
+            if len > MAX_DUMP {
+                return Err(format!("can dump at most {} cells at a time", MAX_DUMP));
+            }
+            let end = addr.saturating_add(len);
+            for row in (addr..end).step_by(8) {
+                let values: Vec<_> = (row..end.min(row.saturating_add(8)))
+                    .map(|a| debugger.computer().peek(a).to_string())
+                    .collect();
+                println!("{:>6}: {}", row, values.join(" "));
+            }
+        }
+        "l" | "list" => {
+            let computer = debugger.computer();
+            let mut next = Some(arg_or(args, 1, computer.inst_ptr())?);
+            let n = arg_or(args, 2, 10)?;
+
+            for _ in 0..n {
+                // The listing ends at the end of the address space.
+                let addr = match next {
+                    Some(addr) => addr,
+                    None => break,
+                };
+                let marker = if addr == computer.inst_ptr() {
+                    "=>"
+                } else {
+                    "  "
+                };
+                match computer.op_at(addr) {
+                    Ok(op) => {
+                        println!("{} {:>6}: {}", marker, addr, op);
+                        next = addr.checked_add(op.size());
+                    }
+                    Err(_) => {
+                        println!("{} {:>6}: data {}", marker, addr, computer.peek(addr));
+                        next = addr.checked_add(1);
+                    }
+                }
+            }
+        }
+        "p" | "poke" => {
+            let addr = arg(args, 1)?;
+            let val = arg(args, 2)?;
+            if !debugger.computer().memory().can_hold(addr) {
+                return Err(format!("address {} is out of range", addr));
+            }
+            debugger.computer_mut().poke(addr, val);
+        }
+        "i" | "input" => {
+            if args.len() < 2 {
+                return Err("expected at least one value".to_string());
+            }
+
+            for i in 1..args.len() {
+                let val = arg(args, i)?;
+                debugger.computer_mut().push_input(val);
+            }
+        }
+        "o" | "outputs" => {
+            let values: Vec<_> = debugger.outputs().iter().map(|v| v.to_string()).collect();
+            println!("{}", values.join(","));
+        }
+        "h" | "help" => println!("{}", HELP),
+        "q" | "quit" => return Ok(false),
+        other => return Err(format!("unknown command {:?}, try help", other)),
+    }
+
+    Ok(true)
+}
+
+fn arg<T: std::str::FromStr>(args: &[&str], idx: usize) -> Result<T, String> {
+    let raw = args
+        .get(idx)
+        .ok_or_else(|| format!("{} expects more arguments", args[0]))?;
+
+    raw.parse()
+        .map_err(|_| format!("invalid argument {:?}", raw))
+}
+
+fn arg_or<T: std::str::FromStr>(args: &[&str], idx: usize, default: T) -> Result<T, String> {
+    match args.get(idx) {
+        Some(_) => arg(args, idx),
+        None => Ok(default),
+    }
+}
+
+fn report(debugger: &Debugger, seen: usize, reason: StopReason) {
+    for val in &debugger.outputs()[seen..] {
+        println!("output: {}", val);
+    }
+
+    match reason {
+        StopReason::Stepped => {}
+        StopReason::Breakpoint(addr) => println!("breakpoint at {}", addr),
+        StopReason::Watchpoint {
+            inst_ptr,
+            addr,
+            access,
+        } => println!(
+            "watchpoint: {:?} of {} by instruction at {}, now {}",
+            access,
+            addr,
+            inst_ptr,
+            debugger.computer().peek(addr)
+        ),
+        StopReason::NeedsInput => println!("waiting for input"),
+        StopReason::Halted => println!("halted"),
+        StopReason::Fault(e) => println!("fault: {}", e),
+    }
+
+    print_current(debugger);
+}
+
+fn print_current(debugger: &Debugger) {
+    let computer = debugger.computer();
+    match computer.op_at(computer.inst_ptr()) {
+        Ok(op) => println!("=> {:>6}: {}", computer.inst_ptr(), op),
+        Err(e) => println!("=> {:>6}: {}", computer.inst_ptr(), e),
+    }
+}
@@ -0,0 +1,301 @@
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Read, StdoutLock, Write};
+use std::process;
+
+use aoc2019::intcode::ascii::{self, AsciiInput, AsciiWriter};
+use aoc2019::intcode::coverage::{Coverage, Style};
+use aoc2019::intcode::io::{Input, Output};
+use aoc2019::intcode::memory::MAX_ADDRESS;
+use aoc2019::intcode::{self, Computer, IntcodeError};
+
+const USAGE: &str = "\
+usage: intcode [options] <program>
+
+Runs an Intcode program, read from a file or from stdin if <program> is -.
+Queued input is consumed first; after that the program reads from stdin,
+unless the program itself came from stdin.
+
+options:
+  -i, --input <values>     queue comma separated input values
+      --input-file <path>  queue input values from a file
+      --text <line>        queue a line of text as character codes
+  -a, --ascii              read stdin as text and print output as text
+  -p, --poke <addr>=<val>  patch memory before running
+      --peek <addr>        print a memory cell once the program halts
+      --coverage <path>    write an lcov record of the run and print a summary
+                           of unexercised opcode forms to stderr
+      --listing <path>     write an annotated coverage listing, as HTML if path
+                           ends in .html, or in colour to stderr if path is -
+  -h, --help               show this message";
+
+struct Options {
+    program: String,
+    inputs: Vec<i64>,
+    ascii: bool,
+    pokes: Vec<(usize, i64)>,
+    peeks: Vec<usize>,
+    coverage: Option<String>,
+    listing: Option<String>,
+}
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("intcode: {}\n\n{}", msg, USAGE);
+    process::exit(2);
+}
+
+fn parse_values(raw: &str) -> Vec<i64> {
+    raw.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            part.parse()
+                .unwrap_or_else(|_| usage_error(&format!("invalid input value {:?}", part)))
+        })
+        .collect()
+}
+
+fn parse_poke(raw: &str) -> (usize, i64) {
+    let mut parts = raw.splitn(2, '=');
+    let addr = parts.next().and_then(|addr| addr.trim().parse().ok());
+    let val = parts.next().and_then(|val| val.trim().parse().ok());
+    match (addr, val) {
+        (Some(addr), Some(val)) if addr <= MAX_ADDRESS => (addr, val),
+        _ => usage_error(&format!("invalid poke {:?}, expected <addr>=<value>", raw)),
+    }
+}
+
+fn read_file(path: &str) -> String {
+    fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("intcode: failed to read {}: {}", path, e);
+        process::exit(1);
+    })
+}
+
+fn parse_args() -> Options {
+    let mut args = env::args().skip(1);
+    let mut program = None;
+    let mut options = Options {
+        program: String::new(),
+        inputs: vec![],
+        ascii: false,
+        pokes: vec![],
+        peeks: vec![],
+        coverage: None,
+        listing: None,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .unwrap_or_else(|| usage_error(&format!("{} needs a value", name)))
+        };
+
+        match arg.as_str() {
+            "-i" | "--input" => options.inputs.extend(parse_values(&value(&arg))),
+            "--input-file" => options
+                .inputs
+                .extend(parse_values(&read_file(&value(&arg)))),
+            "--text" => {
+                options.inputs.extend(ascii::encode(&value(&arg)));
+                options.inputs.push(b'\n' as i64);
+            }
+            "-a" | "--ascii" => options.ascii = true,
+            "-p" | "--poke" => options.pokes.push(parse_poke(&value(&arg))),
+            "--peek" => {
+                let raw = value(&arg);
+                let addr = raw
+                    .parse()
+                    .unwrap_or_else(|_| usage_error(&format!("invalid address {:?}", raw)));
+                options.peeks.push(addr);
+            }
+            "--coverage" => options.coverage = Some(value(&arg)),
+            "--listing" => options.listing = Some(value(&arg)),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with('-') && arg != "-" => {
+                usage_error(&format!("unknown option {}", arg))
+            }
+            _ if program.is_none() => program = Some(arg),
+            _ => usage_error("more than one program given"),
+        }
+    }
+
+    options.program = program.unwrap_or_else(|| usage_error("no program given"));
+    options
+}
+
+/// Reads whitespace or comma separated numbers from stdin as they are needed.
+struct Numbers<R> {
+    reader: R,
+    pending: Vec<i64>,
+}
+
+impl<R: BufRead> Input for Numbers<R> {
+    fn read(&mut self) -> Option<i64> {
+        while self.pending.is_empty() {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) | Err(_) => return None,
+                Ok(_) => {}
+            }
+
+            // A typo mid-run shouldn't end the run, so bad values are reported and skipped.
+            self.pending = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|part| !part.is_empty())
+                .filter_map(|part| match part.parse() {
+                    Ok(val) => Some(val),
+                    Err(_) => {
+                        eprintln!("intcode: invalid input value {:?}", part);
+                        None
+                    }
+                })
+                .collect();
+            self.pending.reverse();
+        }
+
+        self.pending.pop()
+    }
+}
+
+struct NoInput;
+
+impl Input for NoInput {
+    fn read(&mut self) -> Option<i64> {
+        None
+    }
+}
+
+/// Exits once stdout can't be written. A closed pipe, as in `intcode prog | head`, just means
+/// nobody wants more output, so that exits quietly.
+fn output_failed(e: io::Error) -> ! {
+    if e.kind() == io::ErrorKind::BrokenPipe {
+        process::exit(0);
+    }
+    eprintln!("intcode: failed to write output: {}", e);
+    process::exit(1);
+}
+
+/// Prints each output value on its own line.
+struct Print(StdoutLock<'static>);
+
+impl Output for Print {
+    fn write(&mut self, val: i64) {
+        writeln!(self.0, "{}", val).unwrap_or_else(|e| output_failed(e));
+    }
+}
+
+fn run(
+    computer: &mut Computer,
+    input: &mut dyn Input,
+    output: &mut dyn Output,
+    coverage: Option<&mut Coverage>,
+) -> Result<(), IntcodeError> {
+    match coverage {
+        Some(coverage) => computer.run_io_traced(input, output, coverage),
+        None => computer.run_io(input, output),
+    }
+}
+
+fn write_file(path: &str, contents: &str) {
+    fs::write(path, contents).unwrap_or_else(|e| {
+        eprintln!("intcode: failed to write {}: {}", path, e);
+        process::exit(1);
+    });
+}
+
+fn write_coverage(options: &Options, program: &[i64], coverage: &Coverage) {
+    if let Some(path) = &options.coverage {
+        write_file(path, &coverage.to_lcov(program, &options.program));
+        eprint!("{}", coverage);
+    }
+
+    match options.listing.as_deref() {
+        Some("-") => eprint!("{}", coverage.listing(program, Style::Ansi)),
+        Some(path) if path.ends_with(".html") => {
+            write_file(path, &coverage.listing(program, Style::Html))
+        }
+        Some(path) => write_file(path, &coverage.listing(program, Style::Plain)),
+        None => {}
+    }
+}
+
+fn main() {
+    let options = parse_args();
+
+    let from_stdin = options.program == "-";
+    let raw = if from_stdin {
+        let mut raw = String::new();
+        io::stdin().read_to_string(&mut raw).unwrap_or_else(|e| {
+            eprintln!("intcode: failed to read program from stdin: {}", e);
+            process::exit(1);
+        });
+        raw
+    } else {
+        read_file(&options.program)
+    };
+
+    let program = intcode::parse_program(&raw).unwrap_or_else(|e| {
+        eprintln!("intcode: failed to parse {}: {}", options.program, e);
+        process::exit(1);
+    });
+
+    let mut computer = Computer::new(program);
+    for (addr, val) in &options.pokes {
+        computer.poke(*addr, *val);
+    }
+    for val in &options.inputs {
+        computer.push_input(*val);
+    }
+
+    let stdin = io::stdin();
+    let mut input: Box<dyn Input> = match (from_stdin, options.ascii) {
+        (true, _) => Box::new(NoInput),
+        (false, true) => Box::new(AsciiInput::new(stdin.lock())),
+        (false, false) => Box::new(Numbers {
+            reader: stdin.lock(),
+            pending: vec![],
+        }),
+    };
+
+    // Coverage is reported against the image that actually ran, pokes included.
+    let (image, mut coverage) = match (&options.coverage, &options.listing) {
+        (None, None) => (vec![], None),
+        _ => {
+            let image = computer.memory().to_vec().unwrap_or_else(|| {
+                eprintln!("intcode: memory is too large to report coverage for");
+                process::exit(1);
+            });
+            (image, Some(Coverage::new()))
+        }
+    };
+
+    let result = if options.ascii {
+        let mut output = AsciiWriter::new(io::stdout());
+        let result = run(&mut computer, &mut *input, &mut output, coverage.as_mut());
+        if let Err(e) = output.finish() {
+            output_failed(e);
+        }
+        result
+    } else {
+        let mut output = Print(io::stdout().lock());
+        run(&mut computer, &mut *input, &mut output, coverage.as_mut())
+    };
+
+    if let Some(coverage) = &coverage {
+        write_coverage(&options, &image, coverage);
+    }
+
+    if let Err(e) = result {
+        eprintln!("intcode: {}", e);
+        process::exit(1);
+    }
+
+    let mut stdout = io::stdout().lock();
+    for addr in &options.peeks {
+        writeln!(stdout, "[{}] = {}", addr, computer.peek(*addr))
+            .unwrap_or_else(|e| output_failed(e));
+    }
+}
@@ -0,0 +1,1068 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+use std::io::Read;
+use std::num::ParseIntError;
+
+pub mod amplifier;
+pub mod analyze;
+pub mod ascii;
+pub mod asm;
+pub mod asynchronous;
+pub mod budget;
+mod cache;
+pub mod compile;
+pub mod conformance;
+pub mod coverage;
+pub mod debug;
+pub mod disasm;
+pub mod fuzz;
+pub mod io;
+pub mod memory;
+pub mod network;
+pub mod reverse;
+pub mod snapshot;
+pub mod trace;
+pub mod word;
+
+use self::io::{Input, Output};
+use budget::{Budget, Outcome};
+use cache::DecodeCache;
+use memory::Memory;
+use snapshot::{Snapshot, SnapshotError};
+use trace::{MemoryWrite, Operand, TraceEvent, Tracer};
+use word::Word;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamMode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+impl ParamMode {
+    /// The digit selecting this mode in an encoded opcode.
+    pub fn digit(self) -> i64 {
+        match self {
+            ParamMode::Position => 0,
+            ParamMode::Immediate => 1,
+            ParamMode::Relative => 2,
+        }
+    }
+}
+
+pub type ParamWithMode<W = i64> = (ParamMode, W);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op<W = i64> {
+    Add(ParamWithMode<W>, ParamWithMode<W>, ParamWithMode<W>),
+    Mul(ParamWithMode<W>, ParamWithMode<W>, ParamWithMode<W>),
+    Input(ParamWithMode<W>),
+    Output(ParamWithMode<W>),
+    JumpIfTrue(ParamWithMode<W>, ParamWithMode<W>),
+    JumpIfFalse(ParamWithMode<W>, ParamWithMode<W>),
+    LessThan(ParamWithMode<W>, ParamWithMode<W>, ParamWithMode<W>),
+    Equals(ParamWithMode<W>, ParamWithMode<W>, ParamWithMode<W>),
+    ModifyRelativeBase(ParamWithMode<W>),
+    Halt,
+}
+
+impl<W: Word> Op<W> {
+    /// Decodes the instruction starting at `words[0]`. `addr` is only used to report errors.
+    pub fn decode(addr: usize, words: &[W]) -> Result<Op<W>> {
+        Self::decode_from(addr, words.len(), |n| words[n].clone())
+    }
+
+    /// Decodes an instruction from the first `available` words returned by `word`.
+    fn decode_from(addr: usize, available: usize, get: impl Fn(usize) -> W) -> Result<Op<W>> {
+        let raw_op = if available > 0 {
+            word::saturate(&get(0))
+        } else {
+            0
+        };
+        let opcode = raw_op % 100;
+        let mut raw_param_modes = raw_op / 100;
+        let mut param_modes = [ParamMode::Position; 3];
+        let mut digit = 0;
+
+        // Every mode digit is validated, even ones past the instruction's last parameter.
+        while raw_param_modes > 0 {
+            let param_mode = match raw_param_modes % 10 {
+                0 => ParamMode::Position,
+                1 => ParamMode::Immediate,
+                2 => ParamMode::Relative,
+                mode => {
+                    return Err(IntcodeError::UnknownParamMode {
+                        inst_ptr: addr,
+                        opcode: raw_op,
+                        mode,
+                    })
+                }
+            };
+
+            if let Some(slot) = param_modes.get_mut(digit) {
+                *slot = param_mode;
+            }
+            digit += 1;
+            raw_param_modes /= 10;
+        }
+
+        let num_params = match opcode {
+            1 | 2 | 7 | 8 => 3,
+            5 | 6 => 2,
+            3 | 4 | 9 => 1,
+            99 => 0,
+            _ => {
+                return Err(IntcodeError::UnknownOpcode {
+                    inst_ptr: addr,
+                    opcode: raw_op,
+                })
+            }
+        };
+
+        if num_params >= available {
+            return Err(IntcodeError::TruncatedInstruction {
+                inst_ptr: addr,
+                opcode: raw_op,
+            });
+        }
+
+        let param = |n: usize| (param_modes[n - 1], get(n));
+
+        Ok(match opcode {
+            1 => Op::Add(param(1), param(2), param(3)),
+            2 => Op::Mul(param(1), param(2), param(3)),
+            3 => Op::Input(param(1)),
+            4 => Op::Output(param(1)),
+            5 => Op::JumpIfTrue(param(1), param(2)),
+            6 => Op::JumpIfFalse(param(1), param(2)),
+            7 => Op::LessThan(param(1), param(2), param(3)),
+            8 => Op::Equals(param(1), param(2), param(3)),
+            9 => Op::ModifyRelativeBase(param(1)),
+            _ => Op::Halt,
+        })
+    }
+
+    /// The opcode without its parameter modes.
+    pub fn opcode(&self) -> i64 {
+        match self {
+            Op::Add(_, _, _) => 1,
+            Op::Mul(_, _, _) => 2,
+            Op::Input(_) => 3,
+            Op::Output(_) => 4,
+            Op::JumpIfTrue(_, _) => 5,
+            Op::JumpIfFalse(_, _) => 6,
+            Op::LessThan(_, _, _) => 7,
+            Op::Equals(_, _, _) => 8,
+            Op::ModifyRelativeBase(_) => 9,
+            Op::Halt => 99,
+        }
+    }
+
+    /// The assembler mnemonic for this instruction.
+    pub fn mnemonic(&self) -> &'static str {
+        let opcode = self.opcode();
+        asm::MNEMONICS
+            .iter()
+            .find(|(_, op, _, _)| *op == opcode)
+            .map(|(name, _, _, _)| *name)
+            .unwrap()
+    }
+
+    pub fn params(&self) -> Vec<ParamWithMode<W>> {
+        match self {
+            Op::Add(a, b, c) | Op::Mul(a, b, c) | Op::LessThan(a, b, c) | Op::Equals(a, b, c) => {
+                vec![a.clone(), b.clone(), c.clone()]
+            }
+            Op::JumpIfTrue(a, b) | Op::JumpIfFalse(a, b) => vec![a.clone(), b.clone()],
+            Op::Input(a) | Op::Output(a) | Op::ModifyRelativeBase(a) => vec![a.clone()],
+            Op::Halt => vec![],
+        }
+    }
+
+    /// The parameter the instruction writes its result to, if any.
+    pub fn destination(&self) -> Option<ParamWithMode<W>> {
+        match self {
+            Op::Add(_, _, c) | Op::Mul(_, _, c) | Op::LessThan(_, _, c) | Op::Equals(_, _, c) => {
+                Some(c.clone())
+            }
+            Op::Input(a) => Some(a.clone()),
+            _ => None,
+        }
+    }
+
+    /// The parameters the instruction reads from.
+    pub fn sources(&self) -> Vec<ParamWithMode<W>> {
+        let mut params = self.params();
+        if self.destination().is_some() {
+            params.pop();
+        }
+
+        params
+    }
+
+    /// Encodes the instruction in its canonical form, without redundant mode digits.
+    pub fn encode(&self) -> Vec<W> {
+        let params = self.params();
+
+        let mut raw_op = self.opcode();
+        let mut mode_place = 100;
+        for (mode, _) in &params {
+            raw_op += mode_place * mode.digit();
+            mode_place *= 10;
+        }
+
+        let mut words = vec![W::from_i64(raw_op)];
+        words.extend(params.into_iter().map(|(_, val)| val));
+        words
+    }
+
+    /// Number of words the instruction occupies, including the opcode itself.
+    pub fn size(&self) -> usize {
+        match self {
+            Op::Add(_, _, _) | Op::Mul(_, _, _) | Op::LessThan(_, _, _) | Op::Equals(_, _, _) => 4,
+            Op::JumpIfTrue(_, _) | Op::JumpIfFalse(_, _) => 3,
+            Op::Input(_) | Op::Output(_) | Op::ModifyRelativeBase(_) => 2,
+            Op::Halt => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReturnMode<W = i64> {
+    Output(W),
+    /// An `Input` instruction found the input queue empty. The instruction pointer is left on
+    /// it, so pushing a value and running again resumes the program where it stopped.
+    NeedsInput,
+    Halt,
+}
+
+/// A fault raised while executing a program. `inst_ptr` is the address of the faulting
+/// instruction and `opcode` its raw value, parameter modes included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntcodeError {
+    UnknownOpcode {
+        inst_ptr: usize,
+        opcode: i64,
+    },
+    UnknownParamMode {
+        inst_ptr: usize,
+        opcode: i64,
+        mode: i64,
+    },
+    TruncatedInstruction {
+        inst_ptr: usize,
+        opcode: i64,
+    },
+    WriteToImmediate {
+        inst_ptr: usize,
+        opcode: i64,
+    },
+    NegativeAddress {
+        inst_ptr: usize,
+        opcode: i64,
+        address: i64,
+    },
+    InputExhausted {
+        inst_ptr: usize,
+        opcode: i64,
+    },
+    AddressOutOfRange {
+        inst_ptr: usize,
+        opcode: i64,
+        address: i64,
+        limit: usize,
+    },
+    /// An arithmetic result didn't fit in the machine's word type.
+    Overflow {
+        inst_ptr: usize,
+        opcode: i64,
+    },
+}
+
+impl IntcodeError {
+    pub fn inst_ptr(&self) -> usize {
+        match *self {
+            IntcodeError::UnknownOpcode { inst_ptr, .. }
+            | IntcodeError::UnknownParamMode { inst_ptr, .. }
+            | IntcodeError::TruncatedInstruction { inst_ptr, .. }
+            | IntcodeError::WriteToImmediate { inst_ptr, .. }
+            | IntcodeError::NegativeAddress { inst_ptr, .. }
+            | IntcodeError::InputExhausted { inst_ptr, .. }
+            | IntcodeError::AddressOutOfRange { inst_ptr, .. }
+            | IntcodeError::Overflow { inst_ptr, .. } => inst_ptr,
+        }
+    }
+
+    pub fn opcode(&self) -> i64 {
+        match *self {
+            IntcodeError::UnknownOpcode { opcode, .. }
+            | IntcodeError::UnknownParamMode { opcode, .. }
+            | IntcodeError::TruncatedInstruction { opcode, .. }
+            | IntcodeError::WriteToImmediate { opcode, .. }
+            | IntcodeError::NegativeAddress { opcode, .. }
+            | IntcodeError::InputExhausted { opcode, .. }
+            | IntcodeError::AddressOutOfRange { opcode, .. }
+            | IntcodeError::Overflow { opcode, .. } => opcode,
+        }
+    }
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntcodeError::UnknownOpcode { inst_ptr, opcode } => {
+                write!(f, "unknown opcode {} at {}", opcode, inst_ptr)
+            }
+            IntcodeError::UnknownParamMode {
+                inst_ptr,
+                opcode,
+                mode,
+            } => write!(
+                f,
+                "unknown parameter mode {} in opcode {} at {}",
+                mode, opcode, inst_ptr
+            ),
+            IntcodeError::TruncatedInstruction { inst_ptr, opcode } => write!(
+                f,
+                "opcode {} at {} runs past the end of memory",
+                opcode, inst_ptr
+            ),
+            IntcodeError::WriteToImmediate { inst_ptr, opcode } => write!(
+                f,
+                "opcode {} at {} writes to an immediate parameter",
+                opcode, inst_ptr
+            ),
+            IntcodeError::NegativeAddress {
+                inst_ptr,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "opcode {} at {} accesses negative address {}",
+                opcode, inst_ptr, address
+            ),
+            IntcodeError::InputExhausted { inst_ptr, opcode } => {
+                write!(f, "opcode {} at {} has no input to read", opcode, inst_ptr)
+            }
+            IntcodeError::AddressOutOfRange {
+                inst_ptr,
+                opcode,
+                address,
+                limit,
+            } => write!(
+                f,
+                "opcode {} at {} accesses address {} beyond the memory limit of {}",
+                opcode, inst_ptr, address, limit
+            ),
+            IntcodeError::Overflow { inst_ptr, opcode } => {
+                write!(f, "opcode {} at {} overflowed", opcode, inst_ptr)
+            }
+        }
+    }
+}
+
+impl error::Error for IntcodeError {}
+
+pub type Result<T> = std::result::Result<T, IntcodeError>;
+
+#[derive(Debug, Clone)]
+pub struct Computer<W: Word = i64> {
+    ram: Memory<W>,
+    inst_ptr: usize,
+    relative_base: W,
+    inputs: VecDeque<W>,
+    decoded: DecodeCache<W>,
+}
+
+impl<W: Word> Computer<W> {
+    pub fn new(ram: Vec<W>) -> Self {
+        Self::with_memory(Memory::new(ram))
+    }
+
+    /// A machine running on a specific memory backend, e.g. `Memory::dense` or one with a limit.
+    pub fn with_memory(ram: Memory<W>) -> Self {
+        Self {
+            ram,
+            inst_ptr: 0,
+            relative_base: W::zero(),
+            inputs: VecDeque::new(),
+            decoded: DecodeCache::default(),
+        }
+    }
+
+    pub fn inst_ptr(&self) -> usize {
+        self.inst_ptr
+    }
+
+    pub fn relative_base(&self) -> W {
+        self.relative_base.clone()
+    }
+
+    /// One past the highest address that has been loaded or written.
+    pub fn memory_len(&self) -> usize {
+        self.ram.len()
+    }
+
+    /// Reads a memory cell without growing memory; untouched cells read as zero.
+    pub fn peek(&self, addr: usize) -> W {
+        self.ram.get(addr)
+    }
+
+    /// Writes a memory cell. The memory limit only applies to the program, not to pokes.
+    ///
+    /// Panics unless the memory `can_hold` the address.
+    pub fn poke(&mut self, addr: usize, val: W) {
+        self.ram.set(addr, val);
+        self.decoded.invalidate(addr);
+    }
+
+    /// Queues a value to be consumed by the next `Input` instruction.
+    pub fn push_input(&mut self, val: W) {
+        self.inputs.push_back(val);
+    }
+
+    /// Number of queued input values not yet consumed.
+    pub fn pending_inputs(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// A copy of this machine that shares memory pages with it until either side writes to them.
+    /// Much cheaper than cloning the program for every branch of a search.
+    pub fn fork(&self) -> Self {
+        self.clone()
+    }
+
+    pub fn memory(&self) -> &Memory<W> {
+        &self.ram
+    }
+
+    /// Runs the program until it produces an output, runs out of input or halts. Calling this
+    /// again resumes execution where it stopped. On error the instruction pointer is left on the
+    /// faulting instruction.
+    pub fn run_program(&mut self) -> Result<ReturnMode<W>> {
+        loop {
+            if let Some(mode) = self.step()? {
+                return Ok(mode);
+            }
+        }
+    }
+
+    /// Like `run_program`, but gives up once `budget` is exhausted. The machine is left on the
+    /// next instruction to execute, so running again with more budget resumes it.
+    pub fn run_budgeted(&mut self, budget: &mut Budget) -> Result<Outcome<W>> {
+        loop {
+            if let Some(exhausted) = budget.charge() {
+                return Ok(Outcome::BudgetExhausted(exhausted));
+            }
+
+            if let Some(mode) = self.step()? {
+                return Ok(Outcome::Returned(mode));
+            }
+        }
+    }
+
+    /// Runs the program to completion, collecting every output along the way. All input must be
+    /// queued beforehand; blocking on input is reported as `InputExhausted`.
+    pub fn run_to_halt(&mut self) -> Result<Vec<W>> {
+        let mut outputs = vec![];
+        loop {
+            match self.run_program()? {
+                ReturnMode::Output(x) => outputs.push(x),
+                ReturnMode::NeedsInput => return Err(self.input_exhausted()),
+                ReturnMode::Halt => return Ok(outputs),
+            }
+        }
+    }
+
+    /// Decodes the instruction at `addr` without executing it.
+    pub fn op_at(&self, addr: usize) -> Result<Op<W>> {
+        let available = self.ram.len().saturating_sub(addr).min(4);
+        Op::decode_from(addr, available, |n| self.ram.get(addr + n))
+    }
+
+    /// The memory address a parameter refers to given the current relative base, or `None` for
+    /// immediate parameters and relative addresses that overflow the word type.
+    pub fn param_address(&self, param: &ParamWithMode<W>) -> Option<W> {
+        match param {
+            (ParamMode::Position, pos) => Some(pos.clone()),
+            (ParamMode::Immediate, _) => None,
+            (ParamMode::Relative, pos) => pos.try_add(&self.relative_base),
+        }
+    }
+
+    /// Executes a single instruction, returning the `ReturnMode` if it stopped the program.
+    pub fn step(&mut self) -> Result<Option<ReturnMode<W>>> {
+        let op = self.read_op()?;
+        let mut next = self.inst_ptr + op.size();
+
+        match op {
+            Op::Add(a, b, out) => {
+                let val = self.read_param(&a)?.try_add(&self.read_param(&b)?);
+                let val = val.ok_or_else(|| self.overflow())?;
+                self.write_param(&out, val)?;
+            }
+            Op::Mul(a, b, out) => {
+                let val = self.read_param(&a)?.try_mul(&self.read_param(&b)?);
+                let val = val.ok_or_else(|| self.overflow())?;
+                self.write_param(&out, val)?;
+            }
+            Op::Input(out) => {
+                let val = match self.inputs.front() {
+                    Some(val) => val.clone(),
+                    None => return Ok(Some(ReturnMode::NeedsInput)),
+                };
+                self.write_param(&out, val)?;
+                self.inputs.pop_front();
+            }
+            Op::Output(a) => {
+                let val = self.read_param(&a)?;
+                self.inst_ptr = next;
+                return Ok(Some(ReturnMode::Output(val)));
+            }
+            Op::JumpIfTrue(a, b) => {
+                if !self.read_param(&a)?.is_zero() {
+                    next = self.jump_target(&b)?;
+                }
+            }
+            Op::JumpIfFalse(a, b) => {
+                if self.read_param(&a)?.is_zero() {
+                    next = self.jump_target(&b)?;
+                }
+            }
+            Op::LessThan(a, b, out) => {
+                let val = W::from_i64((self.read_param(&a)? < self.read_param(&b)?) as i64);
+                self.write_param(&out, val)?;
+            }
+            Op::Equals(a, b, out) => {
+                let val = W::from_i64((self.read_param(&a)? == self.read_param(&b)?) as i64);
+                self.write_param(&out, val)?;
+            }
+            Op::ModifyRelativeBase(a) => {
+                let base = self.relative_base.try_add(&self.read_param(&a)?);
+                self.relative_base = base.ok_or_else(|| self.overflow())?;
+            }
+            Op::Halt => return Ok(Some(ReturnMode::Halt)),
+        }
+
+        self.inst_ptr = next;
+        Ok(None)
+    }
+
+    /// The raw value of the current instruction, for reporting in errors.
+    fn raw_opcode(&self) -> i64 {
+        word::saturate(&self.peek(self.inst_ptr))
+    }
+
+    fn input_exhausted(&self) -> IntcodeError {
+        IntcodeError::InputExhausted {
+            inst_ptr: self.inst_ptr,
+            opcode: self.raw_opcode(),
+        }
+    }
+
+    fn overflow(&self) -> IntcodeError {
+        IntcodeError::Overflow {
+            inst_ptr: self.inst_ptr,
+            opcode: self.raw_opcode(),
+        }
+    }
+
+    /// Decodes the current instruction, going through the decode cache.
+    fn read_op(&mut self) -> Result<Op<W>> {
+        if let Some(op) = self.decoded.get(self.inst_ptr) {
+            return Ok(op);
+        }
+
+        let op = self.op_at(self.inst_ptr)?;
+        self.decoded
+            .insert(self.inst_ptr, op.clone(), self.ram.len());
+        Ok(op)
+    }
+
+    fn address(&self, address: &W) -> Result<usize> {
+        let wide = address.to_i64();
+        if let Some(Ok(addr)) = wide.map(usize::try_from) {
+            if self.ram.in_bounds(addr) {
+                return Ok(addr);
+            }
+        }
+
+        let wide = wide.unwrap_or_else(|| word::saturate(address));
+        if wide < 0 {
+            return Err(IntcodeError::NegativeAddress {
+                inst_ptr: self.inst_ptr,
+                opcode: self.raw_opcode(),
+                address: wide,
+            });
+        }
+
+        Err(IntcodeError::AddressOutOfRange {
+            inst_ptr: self.inst_ptr,
+            opcode: self.raw_opcode(),
+            address: wide,
+            limit: self.ram.limit().unwrap_or(usize::MAX),
+        })
+    }
+
+    /// The memory address a parameter refers to, checked against the memory bounds, or `None`
+    /// for immediate parameters.
+    fn resolve(&self, param: &ParamWithMode<W>) -> Result<Option<usize>> {
+        match param {
+            (ParamMode::Immediate, _) => Ok(None),
+            _ => match self.param_address(param) {
+                Some(pos) => self.address(&pos).map(Some),
+                None => Err(self.overflow()),
+            },
+        }
+    }
+
+    fn jump_target(&self, param: &ParamWithMode<W>) -> Result<usize> {
+        let target = self.read_param(param)?;
+        self.address(&target)
+    }
+
+    fn read_param(&self, param: &ParamWithMode<W>) -> Result<W> {
+        match self.resolve(param)? {
+            Some(pos) => Ok(self.peek(pos)),
+            None => Ok(param.1.clone()),
+        }
+    }
+
+    fn write_param(&mut self, param: &ParamWithMode<W>, val: W) -> Result<()> {
+        let pos = match self.resolve(param)? {
+            Some(pos) => pos,
+            None => {
+                return Err(IntcodeError::WriteToImmediate {
+                    inst_ptr: self.inst_ptr,
+                    opcode: self.raw_opcode(),
+                })
+            }
+        };
+
+        self.poke(pos, val);
+        Ok(())
+    }
+}
+
+/// Tooling that works on plain `i64` machines only.
+impl Computer {
+    /// Captures memory, registers and queued input so the machine can be resumed later.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory_len: self.ram.len(),
+            pages: Snapshot::pages_of(&self.ram),
+            inst_ptr: self.inst_ptr,
+            relative_base: self.relative_base,
+            inputs: self.inputs.iter().copied().collect(),
+        }
+    }
+
+    /// Replaces the whole machine state with `snapshot`, keeping the memory backend and limit.
+    /// Fails, leaving the machine alone, if the snapshot's memory doesn't fit the backend.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> std::result::Result<(), SnapshotError> {
+        if !self
+            .ram
+            .load_pages(snapshot.memory_len, snapshot.pages.clone())
+        {
+            return Err(SnapshotError::DoesNotFit(snapshot.memory_len));
+        }
+        self.inst_ptr = snapshot.inst_ptr;
+        self.relative_base = snapshot.relative_base;
+        self.inputs = snapshot.inputs.iter().copied().collect();
+        self.decoded.clear();
+        Ok(())
+    }
+
+    /// Runs the program to completion, reading from `input` whenever the queued input runs out
+    /// and writing every output to `output`. Reports `InputExhausted` if `input` ends first.
+    pub fn run_io<I, O>(&mut self, input: &mut I, output: &mut O) -> Result<()>
+    where
+        I: Input + ?Sized,
+        O: Output + ?Sized,
+    {
+        loop {
+            match self.run_program()? {
+                ReturnMode::Output(val) => output.write(val),
+                ReturnMode::NeedsInput => match input.read() {
+                    Some(val) => self.push_input(val),
+                    None => return Err(self.input_exhausted()),
+                },
+                ReturnMode::Halt => return Ok(()),
+            }
+        }
+    }
+
+    /// Like `run_io`, but reports every executed instruction to `tracer`.
+    pub fn run_io_traced<I, O, T>(
+        &mut self,
+        input: &mut I,
+        output: &mut O,
+        tracer: &mut T,
+    ) -> Result<()>
+    where
+        I: Input + ?Sized,
+        O: Output + ?Sized,
+        T: Tracer + ?Sized,
+    {
+        loop {
+            match self.run_traced(tracer)? {
+                ReturnMode::Output(val) => output.write(val),
+                ReturnMode::NeedsInput => match input.read() {
+                    Some(val) => self.push_input(val),
+                    None => return Err(self.input_exhausted()),
+                },
+                ReturnMode::Halt => return Ok(()),
+            }
+        }
+    }
+
+    /// Like `run_program`, but reports every executed instruction to `tracer`.
+    pub fn run_traced<T: Tracer + ?Sized>(&mut self, tracer: &mut T) -> Result<ReturnMode> {
+        loop {
+            if let Some(mode) = self.step_traced(tracer)? {
+                return Ok(mode);
+            }
+        }
+    }
+
+    /// Like `step`, but reports the executed instruction to `tracer`. Nothing is reported when
+    /// the instruction faults or blocks on input.
+    pub fn step_traced<T: Tracer + ?Sized>(
+        &mut self,
+        tracer: &mut T,
+    ) -> Result<Option<ReturnMode>> {
+        let inst_ptr = self.inst_ptr;
+        let relative_base = self.relative_base;
+        let op = self.read_op()?;
+
+        // Blocking on input comes before resolving the destination, as in `step`.
+        if let (Op::Input(_), true) = (op, self.inputs.is_empty()) {
+            return Ok(Some(ReturnMode::NeedsInput));
+        }
+
+        // A jump that isn't taken never reads its target.
+        let taken = |cond: &Operand| match op {
+            Op::JumpIfTrue(_, _) => cond.value != 0,
+            Op::JumpIfFalse(_, _) => cond.value == 0,
+            _ => true,
+        };
+
+        let mut operands = vec![];
+        for param in op.sources() {
+            if !operands.first().is_none_or(taken) {
+                break;
+            }
+            operands.push(Operand {
+                address: self.resolve(&param)?,
+                value: self.read_param(&param)?,
+            });
+        }
+
+        let dest = match op.destination() {
+            Some(param) => self.resolve(&param)?,
+            None => None,
+        };
+        let old = dest.map(|addr| self.peek(addr));
+
+        let mode = self.step()?;
+        if mode == Some(ReturnMode::NeedsInput) {
+            return Ok(mode);
+        }
+
+        let write = dest.map(|addr| MemoryWrite {
+            addr,
+            old: old.unwrap_or(0),
+            new: self.peek(addr),
+        });
+
+        tracer.trace(&TraceEvent {
+            inst_ptr,
+            relative_base,
+            op,
+            operands,
+            write,
+            next_inst_ptr: self.inst_ptr,
+        });
+
+        Ok(mode)
+    }
+}
+
+/// Resumes a snapshot on paged memory with no limit.
+impl TryFrom<Snapshot> for Computer {
+    type Error = SnapshotError;
+
+    fn try_from(snapshot: Snapshot) -> std::result::Result<Self, SnapshotError> {
+        let mut ram = Memory::default();
+        if !ram.load_pages(snapshot.memory_len, snapshot.pages) {
+            return Err(SnapshotError::DoesNotFit(snapshot.memory_len));
+        }
+
+        Ok(Self {
+            ram,
+            inst_ptr: snapshot.inst_ptr,
+            relative_base: snapshot.relative_base,
+            inputs: snapshot.inputs.into(),
+            decoded: DecodeCache::default(),
+        })
+    }
+}
+
+pub fn read_input(mut r: impl Read) -> Vec<i64> {
+    let mut raw = String::new();
+    r.read_to_string(&mut raw).unwrap();
+
+    parse_program(&raw).unwrap()
+}
+
+/// Parses a comma separated Intcode program.
+pub fn parse_program(raw: &str) -> std::result::Result<Vec<i64>, ParseIntError> {
+    raw.trim()
+        .split(',')
+        .map(|part| part.trim().parse::<i64>())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_base_quine() {
+        let ram = read_input(&b"109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99"[..]);
+        let mut computer = Computer::new(ram.clone());
+        assert_eq!(ram, computer.run_to_halt().unwrap());
+    }
+
+    #[test]
+    fn large_numbers() {
+        let ram = read_input(&b"104,1125899906842624,99"[..]);
+        let mut computer = Computer::new(ram);
+        assert_eq!(vec![1125899906842624], computer.run_to_halt().unwrap());
+    }
+
+    #[test]
+    fn input_is_consumed_in_order() {
+        let ram = read_input(&b"3,0,3,1,4,1,4,0,99"[..]);
+        let mut computer = Computer::new(ram);
+        computer.push_input(7);
+        computer.push_input(8);
+        assert_eq!(vec![8, 7], computer.run_to_halt().unwrap());
+    }
+
+    #[test]
+    fn memory_grows_on_write() {
+        let ram = read_input(&b"1101,2,3,1000,99"[..]);
+        let mut computer = Computer::new(ram);
+        assert_eq!(ReturnMode::Halt, computer.run_program().unwrap());
+        assert_eq!(5, computer.peek(1000));
+        assert_eq!(0, computer.peek(2000));
+    }
+
+    #[test]
+    fn forks_are_independent() {
+        let ram = read_input(&b"3,0,4,0,99"[..]);
+        let parent = Computer::new(ram);
+
+        let mut children: Vec<_> = (0..3).map(|_| parent.fork()).collect();
+        for (i, child) in children.iter_mut().enumerate() {
+            child.push_input(i as i64);
+            assert_eq!(ReturnMode::Output(i as i64), child.run_program().unwrap());
+        }
+
+        assert_eq!(3, parent.peek(0));
+        assert_eq!(0, parent.inst_ptr());
+        assert_eq!(1, parent.fork().memory().shared_pages());
+    }
+
+    #[test]
+    fn huge_addresses_are_sparse() {
+        let ram = read_input(&b"1101,1,2,1000000000000,4,1000000000000,99"[..]);
+        let mut computer = Computer::new(ram);
+        assert_eq!(vec![3], computer.run_to_halt().unwrap());
+        assert_eq!(2, computer.memory().pages());
+        assert_eq!(computer.memory(), computer.fork().memory());
+    }
+
+    #[test]
+    fn memory_limit() {
+        let ram = read_input(&b"1101,1,2,100,99"[..]);
+        let mut computer = Computer::with_memory(Memory::dense(ram).with_limit(100));
+        assert_eq!(
+            IntcodeError::AddressOutOfRange {
+                inst_ptr: 0,
+                opcode: 1101,
+                address: 100,
+                limit: 100,
+            },
+            computer.run_program().unwrap_err()
+        );
+        assert_eq!(5, computer.memory_len());
+
+        let ram = read_input(&b"1105,1,100"[..]);
+        let mut computer = Computer::with_memory(Memory::new(ram).with_limit(100));
+        assert!(matches!(
+            computer.run_program(),
+            Err(IntcodeError::AddressOutOfRange { address: 100, .. })
+        ));
+    }
+
+    #[test]
+    fn dense_memory_runs_programs() {
+        let ram = read_input(&b"109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99"[..]);
+        let mut computer = Computer::with_memory(Memory::dense(ram.clone()));
+        assert_eq!(ram, computer.run_to_halt().unwrap());
+        assert!(computer.memory().is_dense());
+    }
+
+    #[test]
+    fn dense_memory_refuses_huge_addresses() {
+        let ram = read_input(&b"1101,1,2,1000000000000,99"[..]);
+        let mut computer = Computer::with_memory(Memory::dense(ram));
+        assert!(matches!(
+            computer.run_program(),
+            Err(IntcodeError::AddressOutOfRange {
+                limit: memory::VEC_LIMIT,
+                ..
+            })
+        ));
+        assert_eq!(5, computer.memory_len());
+        assert!(!computer.memory().can_hold(memory::VEC_LIMIT));
+    }
+
+    #[test]
+    fn rewritten_instructions_are_decoded_again() {
+        // Loops over `out 5`, bumping its operand each time until it reaches 7.
+        let program = "104,5,1001,1,1,1,1008,1,8,17,1005,17,16,1105,1,0,99";
+        let mut computer = Computer::new(read_input(program.as_bytes()));
+        assert_eq!(vec![5, 6, 7], computer.run_to_halt().unwrap());
+
+        let mut computer = Computer::new(read_input(program.as_bytes()));
+        assert_eq!(ReturnMode::Output(5), computer.run_program().unwrap());
+        computer.poke(1, 100);
+        assert_eq!(ReturnMode::Output(101), computer.run_program().unwrap());
+    }
+
+    fn run(program: &str) -> Result<Vec<i64>> {
+        Computer::new(read_input(program.as_bytes())).run_to_halt()
+    }
+
+    #[test]
+    fn unknown_opcode() {
+        let err = run("1101,1,1,0,42,99").unwrap_err();
+        assert_eq!(
+            IntcodeError::UnknownOpcode {
+                inst_ptr: 4,
+                opcode: 42
+            },
+            err
+        );
+    }
+
+    #[test]
+    fn unknown_param_mode() {
+        let err = run("304,0,99").unwrap_err();
+        assert_eq!(
+            IntcodeError::UnknownParamMode {
+                inst_ptr: 0,
+                opcode: 304,
+                mode: 3
+            },
+            err
+        );
+    }
+
+    #[test]
+    fn write_to_immediate() {
+        let err = run("11101,1,1,0,99").unwrap_err();
+        assert_eq!(
+            IntcodeError::WriteToImmediate {
+                inst_ptr: 0,
+                opcode: 11101
+            },
+            err
+        );
+    }
+
+    #[test]
+    fn negative_addresses() {
+        assert_eq!(
+            Err(IntcodeError::NegativeAddress {
+                inst_ptr: 0,
+                opcode: 4,
+                address: -1
+            }),
+            run("4,-1,99")
+        );
+        assert_eq!(
+            Err(IntcodeError::NegativeAddress {
+                inst_ptr: 2,
+                opcode: 1201,
+                address: -6
+            }),
+            run("109,-5,1201,-1,0,0,99")
+        );
+        assert_eq!(
+            Err(IntcodeError::NegativeAddress {
+                inst_ptr: 0,
+                opcode: 1105,
+                address: -3
+            }),
+            run("1105,1,-3")
+        );
+    }
+
+    #[test]
+    fn truncated_instruction() {
+        let err = run("1,0,0").unwrap_err();
+        assert_eq!(
+            IntcodeError::TruncatedInstruction {
+                inst_ptr: 0,
+                opcode: 1
+            },
+            err
+        );
+    }
+
+    #[test]
+    fn missing_input() {
+        let mut computer = Computer::new(read_input(&b"3,0,99"[..]));
+        let err = computer.run_to_halt().unwrap_err();
+        assert_eq!(0, err.inst_ptr());
+        assert_eq!(3, err.opcode());
+        assert_eq!(0, computer.inst_ptr());
+    }
+
+    #[test]
+    fn resumes_after_needing_input() {
+        // Echoes its input back until it reads a zero.
+        let ram = read_input(&b"3,100,4,100,1005,100,0,99"[..]);
+        let mut computer = Computer::new(ram);
+
+        assert_eq!(ReturnMode::NeedsInput, computer.run_program().unwrap());
+        assert_eq!(ReturnMode::NeedsInput, computer.run_program().unwrap());
+        assert_eq!(0, computer.inst_ptr());
+
+        computer.push_input(5);
+        assert_eq!(ReturnMode::Output(5), computer.run_program().unwrap());
+        assert_eq!(ReturnMode::NeedsInput, computer.run_program().unwrap());
+
+        computer.push_input(0);
+        assert_eq!(ReturnMode::Output(0), computer.run_program().unwrap());
+        assert_eq!(ReturnMode::Halt, computer.run_program().unwrap());
+    }
+
+    #[test]
+    fn running_off_the_end_of_memory() {
+        let err = run("1105,1,100").unwrap_err();
+        assert_eq!(
+            IntcodeError::UnknownOpcode {
+                inst_ptr: 100,
+                opcode: 0
+            },
+            err
+        );
+    }
+}
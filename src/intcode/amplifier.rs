@@ -0,0 +1,301 @@
+//! Searches for the phase settings that maximise the signal out of a series of amplifiers.
+//!
+//! Each amplifier runs a copy of the same program, reads its phase setting followed by a
+//! signal, and outputs the amplified signal. In a `Chain` the first amplifier receives the
+//! initial signal and the last one's output is the result; in a `Feedback` loop the last
+//! amplifier also feeds the first, and the result is its final output once everything halts.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::error;
+use std::fmt;
+use std::thread;
+
+use itertools::Itertools;
+
+use super::network::{Network, NodeState};
+use super::IntcodeError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    Chain,
+    Feedback,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AmplifierError {
+    Fault {
+        phases: Vec<i64>,
+        amplifier: usize,
+        error: IntcodeError,
+    },
+    /// The amplifiers stopped without every one halting, or the last produced no signal.
+    Stalled { phases: Vec<i64> },
+    /// There were no phase settings, so no amplifiers to run.
+    NoAmplifiers,
+}
+
+impl fmt::Display for AmplifierError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AmplifierError::Fault {
+                phases,
+                amplifier,
+                error,
+            } => write!(
+                f,
+                "amplifier {} faulted with phases {:?}: {}",
+                amplifier, phases, error
+            ),
+            AmplifierError::Stalled { phases } => {
+                write!(f, "amplifiers stalled with phases {:?}", phases)
+            }
+            AmplifierError::NoAmplifiers => write!(f, "no amplifiers to run"),
+        }
+    }
+}
+
+impl error::Error for AmplifierError {}
+
+pub type Result<T> = std::result::Result<T, AmplifierError>;
+
+/// A result kept by `PhaseSearch::top`: the weakest signal sorts greatest, with ties broken
+/// against later orderings, so the heap's top is the first to drop.
+type Ranked = (Reverse<i64>, usize, Vec<i64>);
+
+/// Runs one amplifier per phase setting, returning the final signal.
+pub fn amplify(program: &[i64], phases: &[i64], topology: Topology, signal: i64) -> Result<i64> {
+    if phases.is_empty() {
+        return Err(AmplifierError::NoAmplifiers);
+    }
+
+    let mut network = Network::new();
+    let amps = network.add_nodes(program, phases.len());
+    match topology {
+        Topology::Chain => network.chain(&amps),
+        Topology::Feedback => network.ring(&amps),
+    };
+
+    for (amp, phase) in amps.iter().zip(phases) {
+        network.seed(*amp, *phase);
+    }
+    if let Some(first) = amps.first() {
+        network.seed(*first, signal);
+    }
+
+    let report = network.run();
+    if let Some((amplifier, error)) = report.faults().into_iter().next() {
+        return Err(AmplifierError::Fault {
+            phases: phases.to_vec(),
+            amplifier,
+            error,
+        });
+    }
+
+    let stalled = || AmplifierError::Stalled {
+        phases: phases.to_vec(),
+    };
+
+    // In a chain the last amplifier halting is all that matters; earlier ones may keep
+    // waiting for input nobody sends.
+    let last = *amps.last().ok_or_else(stalled)?;
+    let finished = match topology {
+        Topology::Chain => report.states[last] == NodeState::Halted,
+        Topology::Feedback => report.all_halted(),
+    };
+    if !finished {
+        return Err(stalled());
+    }
+
+    report.last_output(last).ok_or_else(stalled)
+}
+
+/// A configurable search over every ordering of distinct phase settings.
+#[derive(Debug, Clone)]
+pub struct PhaseSearch<'a> {
+    program: &'a [i64],
+    phases: Vec<i64>,
+    amplifiers: usize,
+    topology: Topology,
+    signal: i64,
+    threads: usize,
+}
+
+impl<'a> PhaseSearch<'a> {
+    /// Searches chains with one amplifier per phase setting, starting from a signal of 0.
+    pub fn new(program: &'a [i64], phases: &[i64]) -> Self {
+        Self {
+            program,
+            phases: phases.to_vec(),
+            amplifiers: phases.len(),
+            topology: Topology::Chain,
+            signal: 0,
+            threads: 1,
+        }
+    }
+
+    /// Uses `count` amplifiers, each with a different phase setting from the candidates.
+    pub fn amplifiers(mut self, count: usize) -> Self {
+        self.amplifiers = count;
+        self
+    }
+
+    pub fn topology(mut self, topology: Topology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// The signal fed to the first amplifier.
+    pub fn signal(mut self, signal: i64) -> Self {
+        self.signal = signal;
+        self
+    }
+
+    /// Spreads the search over `threads` threads.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Spreads the search over as many threads as the machine has cores.
+    pub fn parallel(self) -> Self {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        self.threads(threads)
+    }
+
+    /// The best phase ordering and its signal, or `None` if there are no amplifiers or fewer
+    /// candidate phases than amplifiers. Ties go to the ordering that comes first.
+    pub fn best(&self) -> Result<Option<(Vec<i64>, i64)>> {
+        Ok(self.top(1)?.into_iter().next())
+    }
+
+    /// Every phase ordering with its signal, strongest first. This keeps every result, so
+    /// prefer `best` or `top` for large searches.
+    pub fn ranked(&self) -> Result<Vec<(Vec<i64>, i64)>> {
+        self.top(usize::MAX)
+    }
+
+    /// The `k` strongest phase orderings with their signals, strongest first, with ties in
+    /// the order the orderings come in. Orderings are generated as they are tried, and only
+    /// the `k` best results are held at a time.
+    pub fn top(&self, k: usize) -> Result<Vec<(Vec<i64>, i64)>> {
+        if self.amplifiers == 0 {
+            return Ok(vec![]);
+        }
+
+        let mut heap = if self.threads == 1 {
+            self.search(0, k)?
+        } else {
+            thread::scope(|scope| {
+                let handles: Vec<_> = (0..self.threads)
+                    .map(|offset| scope.spawn(move || self.search(offset, k)))
+                    .collect();
+
+                let mut heap = BinaryHeap::new();
+                for handle in handles {
+                    heap.extend(handle.join().unwrap()?);
+                }
+                Ok(heap)
+            })?
+        };
+
+        while heap.len() > k {
+            heap.pop();
+        }
+        Ok(heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|(Reverse(signal), _, phases)| (phases, signal))
+            .collect())
+    }
+
+    /// Tries every `threads`th ordering, starting with the `offset`th, keeping the best `k`.
+    fn search(&self, offset: usize, k: usize) -> Result<BinaryHeap<Ranked>> {
+        let mut heap = BinaryHeap::new();
+        let orderings = self
+            .phases
+            .iter()
+            .copied()
+            .permutations(self.amplifiers)
+            .enumerate()
+            .skip(offset)
+            .step_by(self.threads);
+
+        for (idx, phases) in orderings {
+            let signal = amplify(self.program, &phases, self.topology, self.signal)?;
+            heap.push((Reverse(signal), idx, phases));
+            if heap.len() > k {
+                heap.pop();
+            }
+        }
+        Ok(heap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::read_input;
+    use crate::util;
+
+    const CHAIN: &str = "3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0";
+
+    #[test]
+    fn any_number_of_amplifiers() {
+        let program = read_input(CHAIN.as_bytes());
+
+        let search = PhaseSearch::new(&program, &[0, 1, 2]);
+        assert_eq!(Some((vec![2, 1, 0], 210)), search.best().unwrap());
+
+        let search = PhaseSearch::new(&program, &[0, 1, 2, 3, 4, 5, 6]).amplifiers(3);
+        assert_eq!(Some((vec![6, 5, 4], 654)), search.best().unwrap());
+        assert_eq!(7 * 6 * 5, search.ranked().unwrap().len());
+
+        let search = PhaseSearch::new(&program, &[1, 2]).amplifiers(3);
+        assert_eq!(None, search.best().unwrap());
+
+        let search = PhaseSearch::new(&program, &[1, 2]).amplifiers(0);
+        assert_eq!(None, search.best().unwrap());
+        assert_eq!(
+            Err(AmplifierError::NoAmplifiers),
+            amplify(&program, &[], Topology::Chain, 0)
+        );
+    }
+
+    #[test]
+    fn ranked_list() {
+        let program = read_input(CHAIN.as_bytes());
+        let ranked = PhaseSearch::new(&program, &[1, 2]).ranked().unwrap();
+        assert_eq!(vec![(vec![2, 1], 21), (vec![1, 2], 12)], ranked);
+
+        let search = PhaseSearch::new(&program, &[0, 1, 2, 3]);
+        let ranked = search.ranked().unwrap();
+        assert_eq!(ranked[..3], search.top(3).unwrap()[..]);
+        assert_eq!(ranked[..3], search.threads(3).top(3).unwrap()[..]);
+    }
+
+    #[test]
+    fn parallel_matches_serial() {
+        let program = read_input(&util::read_input_file("day7.txt")[..]);
+        let search = PhaseSearch::new(&program, &[5, 6, 7, 8, 9]).topology(Topology::Feedback);
+
+        let serial = search.ranked().unwrap();
+        assert_eq!(serial, search.clone().threads(4).ranked().unwrap());
+        assert_eq!((vec![6, 7, 9, 8, 5], 61379886), serial[0]);
+    }
+
+    #[test]
+    fn reports_faults_and_stalls() {
+        let program = read_input(&b"3,0,3,0,3,0,99"[..]);
+        assert_eq!(
+            Err(AmplifierError::Stalled { phases: vec![1] }),
+            amplify(&program, &[1], Topology::Chain, 7)
+        );
+
+        let program = read_input(&b"3,0,3,0,42"[..]);
+        assert!(matches!(
+            amplify(&program, &[1, 2], Topology::Chain, 0),
+            Err(AmplifierError::Fault { amplifier: 0, .. })
+        ));
+    }
+}
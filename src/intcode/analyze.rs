@@ -0,0 +1,288 @@
+//! Static analysis of Intcode programs, for vetting them before they are run.
+//!
+//! The analysis works on the code `Disassembly` finds by following control flow from address 0,
+//! split into basic blocks. Jumps through memory can't be followed, so when the report lists any
+//! `IndirectJump`s the unreachable ranges may include code that does run. Writes through the
+//! relative base can't be resolved either, which makes the memory estimate a lower bound.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use super::disasm::{self, Disassembly};
+use super::{Op, ParamMode};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Block {
+    pub start: usize,
+    /// One past the last word of the block's last instruction.
+    pub end: usize,
+    pub successors: Vec<usize>,
+    /// The block ends in a jump whose target is read from memory.
+    pub indirect_jump: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Finding {
+    /// The instruction at `addr` writes into the instruction starting at `target`.
+    SelfModifying { addr: usize, target: usize },
+    /// The instruction at `addr` has an immediate destination and will fault when run.
+    ImmediateWrite { addr: usize },
+    /// The instruction at `addr` jumps to an address read from memory.
+    IndirectJump { addr: usize },
+}
+
+/// Words `start..end` are never reached by static control flow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Unreachable {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Report {
+    pub program_len: usize,
+    pub instructions: usize,
+    pub blocks: Vec<Block>,
+    pub findings: Vec<Finding>,
+    pub unreachable: Vec<Unreachable>,
+    /// The highest address the program or any position-mode parameter refers to.
+    pub max_address: usize,
+    /// False when relative-mode accesses could reach past `max_address`.
+    pub max_address_exact: bool,
+}
+
+impl Report {
+    pub fn new(program: &[i64]) -> Self {
+        let disassembly = Disassembly::new(program);
+        let instructions = disassembly.instructions();
+
+        let mut findings = vec![];
+        let mut max_address = program.len().saturating_sub(1);
+        let mut max_address_exact = true;
+
+        for (&addr, op) in instructions {
+            if let Some((mode, val)) = op.destination() {
+                match mode {
+                    ParamMode::Immediate => findings.push(Finding::ImmediateWrite { addr }),
+                    ParamMode::Position if val >= 0 => {
+                        if let Some(target) = disassembly.instruction_containing(val as usize) {
+                            findings.push(Finding::SelfModifying { addr, target });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            if is_indirect_jump(op) {
+                findings.push(Finding::IndirectJump { addr });
+            }
+
+            for (mode, val) in op.params() {
+                match mode {
+                    ParamMode::Position if val >= 0 => max_address = max_address.max(val as usize),
+                    ParamMode::Relative => max_address_exact = false,
+                    _ => {}
+                }
+            }
+        }
+
+        Self {
+            program_len: program.len(),
+            instructions: instructions.len(),
+            blocks: blocks(instructions),
+            findings,
+            unreachable: unreachable(&disassembly),
+            max_address,
+            max_address_exact,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("reports always serialize")
+    }
+
+    /// The control-flow graph in Graphviz DOT format, with each block's instructions as its
+    /// label. Indirect jumps lead to a dashed `?` node.
+    pub fn to_dot(&self, program: &[i64]) -> String {
+        let disassembly = Disassembly::new(program);
+        let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+
+        for block in &self.blocks {
+            let mut label = String::new();
+            for (addr, op) in disassembly.instructions().range(block.start..block.end) {
+                write!(label, "{}: {}\\l", addr, op).unwrap();
+            }
+            writeln!(dot, "    b{} [label=\"{}\"];", block.start, escape(&label)).unwrap();
+        }
+
+        let mut indirect = false;
+        for block in &self.blocks {
+            for succ in &block.successors {
+                writeln!(dot, "    b{} -> b{};", block.start, succ).unwrap();
+            }
+            if block.indirect_jump {
+                indirect = true;
+                writeln!(dot, "    b{} -> indirect [style=dashed];", block.start).unwrap();
+            }
+        }
+
+        if indirect {
+            dot.push_str("    indirect [label=\"?\", shape=circle, style=dashed];\n");
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn is_indirect_jump(op: &Op) -> bool {
+    match op {
+        Op::JumpIfTrue(_, (mode, _)) | Op::JumpIfFalse(_, (mode, _)) => {
+            *mode != ParamMode::Immediate
+        }
+        _ => false,
+    }
+}
+
+fn is_terminator(op: &Op) -> bool {
+    matches!(op, Op::JumpIfTrue(_, _) | Op::JumpIfFalse(_, _) | Op::Halt)
+}
+
+fn blocks(instructions: &BTreeMap<usize, Op>) -> Vec<Block> {
+    let mut leaders = BTreeSet::new();
+    leaders.insert(0);
+    for (&addr, op) in instructions {
+        if is_terminator(op) {
+            leaders.extend(disasm::successors(addr, op));
+        }
+    }
+
+    let mut blocks: Vec<Block> = vec![];
+    for (&addr, op) in instructions {
+        let continues = match blocks.last() {
+            Some(block) => block.end == addr && !leaders.contains(&addr),
+            None => false,
+        };
+        if !continues {
+            blocks.push(Block {
+                start: addr,
+                end: addr,
+                successors: vec![],
+                indirect_jump: false,
+            });
+        }
+
+        let block = blocks.last_mut().unwrap();
+        block.end = addr + op.size();
+        block.successors = disasm::successors(addr, op)
+            .into_iter()
+            .filter(|succ| instructions.contains_key(succ))
+            .collect();
+        block.indirect_jump = is_indirect_jump(op);
+    }
+
+    blocks
+}
+
+fn unreachable(disassembly: &Disassembly) -> Vec<Unreachable> {
+    let mut ranges: Vec<Unreachable> = vec![];
+    for addr in 0..disassembly.program().len() {
+        if disassembly.is_code(addr) {
+            continue;
+        }
+
+        match ranges.last_mut() {
+            Some(range) if range.end == addr => range.end += 1,
+            _ => ranges.push(Unreachable {
+                start: addr,
+                end: addr + 1,
+            }),
+        }
+    }
+
+    ranges
+}
+
+fn escape(label: &str) -> String {
+    label.replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+    use crate::intcode::read_input;
+    use crate::util;
+
+    #[test]
+    fn splits_blocks_at_jumps() {
+        let program = assemble(
+            "
+                    in [n]
+            loop:   out [n]
+                    add [n], -1, [n]
+                    jt [n], loop
+                    hlt
+            n:      data 0
+            ",
+        )
+        .unwrap();
+        let report = Report::new(&program);
+
+        let spans: Vec<_> = report
+            .blocks
+            .iter()
+            .map(|b| (b.start, b.end, b.successors.clone()))
+            .collect();
+        assert_eq!(
+            vec![(0, 2, vec![2]), (2, 11, vec![2, 11]), (11, 12, vec![])],
+            spans
+        );
+        assert!(report.findings.is_empty());
+        assert_eq!(vec![Unreachable { start: 12, end: 13 }], report.unreachable);
+        assert_eq!((12, true), (report.max_address, report.max_address_exact));
+    }
+
+    #[test]
+    fn flags_risky_instructions() {
+        // Patches its own output, writes to an immediate and jumps through memory.
+        let program = vec![1101, 0, 42, 5, 104, 0, 11101, 1, 1, 1, 106, 0, 13, 99];
+        let report = Report::new(&program);
+        assert_eq!(
+            vec![
+                Finding::SelfModifying { addr: 0, target: 4 },
+                Finding::ImmediateWrite { addr: 6 },
+                Finding::IndirectJump { addr: 10 },
+            ],
+            report.findings
+        );
+        assert_eq!(vec![Unreachable { start: 13, end: 14 }], report.unreachable);
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!("self_modifying", json["findings"][0]["kind"]);
+        assert_eq!(4, json["findings"][0]["target"]);
+    }
+
+    #[test]
+    fn relative_accesses_make_the_estimate_inexact() {
+        let program = read_input(&util::read_input_file("day9.txt")[..]);
+        let report = Report::new(&program);
+        assert!(!report.max_address_exact);
+        assert!(report.max_address >= program.len() - 1);
+    }
+
+    #[test]
+    fn dot_output() {
+        let program = vec![1105, 1, 7, 3, 4, 5, 6, 5, 3, 3, 99];
+        let report = Report::new(&program);
+        let dot = report.to_dot(&program);
+        assert!(dot.starts_with("digraph cfg {"), "{}", dot);
+        assert!(dot.contains("b0 [label=\"0: jt 1, 7\\l\"];"), "{}", dot);
+        assert!(dot.contains("b0 -> b7;"), "{}", dot);
+        assert!(dot.contains("b7 -> b10;"), "{}", dot);
+        assert!(dot.contains("b7 -> indirect [style=dashed];"), "{}", dot);
+    }
+}
@@ -0,0 +1,276 @@
+//! Adapters for programs that talk in ASCII.
+//!
+//! Input text is fed to the program one character code at a time, a line at a time, so it can
+//! come from a terminal or a script. Output codes are collected into lines; values outside the
+//! ASCII range are not characters, so they are passed through as raw numbers instead, which is
+//! how such programs usually report a final answer.
+
+use std::io::{self, BufRead, Write};
+
+use super::io::{Input, Output};
+
+const NEWLINE: i64 = b'\n' as i64;
+
+/// A piece of program output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A line of text, without its newline.
+    Line(String),
+    /// A value outside the ASCII range.
+    Raw(i64),
+}
+
+/// The character codes of `text`.
+pub fn encode(text: &str) -> Vec<i64> {
+    text.chars().map(|c| c as i64).collect()
+}
+
+/// Splits output values into lines and raw values. A trailing line without a newline is kept.
+pub fn decode(values: &[i64]) -> Vec<Event> {
+    let mut lines = Lines::new();
+    for val in values {
+        lines.write(*val);
+    }
+    lines.finish()
+}
+
+fn as_ascii(val: i64) -> Option<char> {
+    if (0..=127).contains(&val) {
+        Some(val as u8 as char)
+    } else {
+        None
+    }
+}
+
+/// Reads text a line at a time and feeds it to the program as character codes.
+pub struct AsciiInput<R> {
+    reader: R,
+    pending: Vec<i64>,
+    next: usize,
+}
+
+impl<R: BufRead> AsciiInput<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            pending: vec![],
+            next: 0,
+        }
+    }
+}
+
+/// Input ends at the end of the reader, or when it fails.
+impl<R: BufRead> Input for AsciiInput<R> {
+    fn read(&mut self) -> Option<i64> {
+        if self.next == self.pending.len() {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) | Err(_) => return None,
+                Ok(_) => {}
+            }
+
+            self.pending = encode(&line);
+            self.next = 0;
+        }
+
+        self.next += 1;
+        Some(self.pending[self.next - 1])
+    }
+}
+
+/// Collects output into `Event`s.
+#[derive(Debug, Clone, Default)]
+pub struct Lines {
+    events: Vec<Event>,
+    partial: String,
+}
+
+impl Lines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every complete line and raw value so far.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// The text written since the last newline.
+    pub fn partial(&self) -> &str {
+        &self.partial
+    }
+
+    /// Every event, with any unterminated text as a final line.
+    pub fn finish(mut self) -> Vec<Event> {
+        if !self.partial.is_empty() {
+            self.events.push(Event::Line(self.partial));
+        }
+        self.events
+    }
+}
+
+/// A raw value ends any unterminated text first, so events keep the order they were output in.
+impl Output for Lines {
+    fn write(&mut self, val: i64) {
+        match as_ascii(val) {
+            Some('\n') => {
+                let line = std::mem::take(&mut self.partial);
+                self.events.push(Event::Line(line));
+            }
+            Some(c) => self.partial.push(c),
+            None => {
+                if !self.partial.is_empty() {
+                    let line = std::mem::take(&mut self.partial);
+                    self.events.push(Event::Line(line));
+                }
+                self.events.push(Event::Raw(val));
+            }
+        }
+    }
+}
+
+/// Writes output text to a writer as each line completes, and raw values on lines of their own.
+pub struct AsciiWriter<W: Write> {
+    writer: W,
+    line: Vec<u8>,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> AsciiWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            line: vec![],
+            error: None,
+        }
+    }
+
+    /// Writes any unterminated text, flushes the writer and returns it, or the first error
+    /// any write hit.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+
+        self.writer.write_all(&self.line)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_val(&mut self, val: i64) -> io::Result<()> {
+        match as_ascii(val) {
+            Some(c) => {
+                self.line.push(c as u8);
+                if val == NEWLINE {
+                    self.writer.write_all(&self.line)?;
+                    self.writer.flush()?;
+                    self.line.clear();
+                }
+                Ok(())
+            }
+            None => {
+                if !self.line.is_empty() {
+                    self.writer.write_all(&self.line)?;
+                    self.writer.write_all(b"\n")?;
+                    self.line.clear();
+                }
+                writeln!(self.writer, "{}", val)
+            }
+        }
+    }
+}
+
+impl<W: Write> Output for AsciiWriter<W> {
+    fn write(&mut self, val: i64) {
+        if self.error.is_none() {
+            self.error = self.write_val(val).err();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+    use crate::intcode::{Computer, IntcodeError};
+
+    // Echoes each line it reads, then prints 1000 once it sees an empty line.
+    const ECHO: &str = "
+    line:   in [c]
+            eq [c], 10, [t]
+            jt [t], done
+    loop:   out [c]
+            eq [c], 10, [t]
+            jt [t], line
+            in [c]
+            jt 1, loop
+    done:   out 1000
+            hlt
+    c:      data 0
+    t:      data 0
+    ";
+
+    fn echo() -> Computer {
+        Computer::new(assemble(ECHO).unwrap())
+    }
+
+    #[test]
+    fn round_trips_lines() {
+        let mut input = AsciiInput::new(&b"hello\nworld\n\n"[..]);
+        let mut output = Lines::new();
+        echo().run_io(&mut input, &mut output).unwrap();
+
+        assert_eq!(
+            vec![
+                Event::Line("hello".to_string()),
+                Event::Line("world".to_string()),
+                Event::Raw(1000),
+            ],
+            output.finish()
+        );
+    }
+
+    #[test]
+    fn writer_interleaves_raw_values() {
+        let mut input = AsciiInput::new(&b"go\n\n"[..]);
+        let mut output = AsciiWriter::new(vec![]);
+        echo().run_io(&mut input, &mut output).unwrap();
+        assert_eq!(
+            "go\n1000\n",
+            String::from_utf8(output.finish().unwrap()).unwrap()
+        );
+
+        let mut output = AsciiWriter::new(vec![]);
+        for val in encode("partial").into_iter().chain(vec![-1]) {
+            output.write(val);
+        }
+        assert_eq!(
+            "partial\n-1\n",
+            String::from_utf8(output.finish().unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn input_ends_with_the_reader() {
+        let mut input = AsciiInput::new(&b"no newline"[..]);
+        let mut output = Lines::new();
+        let result = echo().run_io(&mut input, &mut output);
+        assert!(matches!(result, Err(IntcodeError::InputExhausted { .. })));
+        assert_eq!("no newline", output.partial());
+    }
+
+    #[test]
+    fn decodes_output_values() {
+        let mut values = encode("ab\nc");
+        values.insert(1, 200);
+        assert_eq!(
+            vec![
+                Event::Line("a".to_string()),
+                Event::Raw(200),
+                Event::Line("b".to_string()),
+                Event::Line("c".to_string()),
+            ],
+            decode(&values)
+        );
+    }
+}
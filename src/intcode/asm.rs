@@ -0,0 +1,598 @@
+//! Assembler for a small mnemonic language that compiles down to Intcode.
+//!
+//! ```text
+//! ; count down from the input value, printing each step
+//!         in [n]
+//! loop:   out [n]
+//!         add [n], -1, [n]
+//!         jt [n], loop
+//!         hlt
+//! n:      data 0
+//! ```
+//!
+//! Operands are immediate by default. `[expr]` reads or writes memory at `expr` (position mode)
+//! and `[rb + expr]` is relative to the relative base. Expressions support integers, `'c'`
+//! character literals, labels, `$` (the address of the current instruction), `+ - * /` and
+//! parentheses. `data` takes a comma separated list of expressions and `"strings"`.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+use super::ParamMode;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    UnexpectedChar(char),
+    InvalidNumber(String),
+    UnterminatedLiteral,
+    InvalidSyntax(String),
+    UnknownMnemonic(String),
+    OperandCount {
+        mnemonic: String,
+        expected: usize,
+        found: usize,
+    },
+    ImmediateWrite(String),
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+    Overflow,
+    DivisionByZero,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+
+        match &self.kind {
+            AsmErrorKind::UnexpectedChar(c) => write!(f, "unexpected character {:?}", c),
+            AsmErrorKind::InvalidNumber(s) => write!(f, "invalid number {:?}", s),
+            AsmErrorKind::UnterminatedLiteral => write!(f, "unterminated literal"),
+            AsmErrorKind::InvalidSyntax(msg) => write!(f, "{}", msg),
+            AsmErrorKind::UnknownMnemonic(m) => write!(f, "unknown mnemonic {:?}", m),
+            AsmErrorKind::OperandCount {
+                mnemonic,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} takes {} operand(s), found {}",
+                mnemonic, expected, found
+            ),
+            AsmErrorKind::ImmediateWrite(m) => {
+                write!(f, "{} cannot write to an immediate operand", m)
+            }
+            AsmErrorKind::DuplicateLabel(l) => write!(f, "label {:?} is already defined", l),
+            AsmErrorKind::UndefinedLabel(l) => write!(f, "undefined label {:?}", l),
+            AsmErrorKind::Overflow => write!(f, "arithmetic overflow in expression"),
+            AsmErrorKind::DivisionByZero => write!(f, "division by zero in expression"),
+        }
+    }
+}
+
+impl error::Error for AsmError {}
+
+/// Mnemonic, opcode, operand count and the index of the operand written to, if any.
+pub(crate) const MNEMONICS: [(&str, i64, usize, Option<usize>); 10] = [
+    ("add", 1, 3, Some(2)),
+    ("mul", 2, 3, Some(2)),
+    ("in", 3, 1, Some(0)),
+    ("out", 4, 1, None),
+    ("jt", 5, 2, None),
+    ("jf", 6, 2, None),
+    ("lt", 7, 3, Some(2)),
+    ("eq", 8, 3, Some(2)),
+    ("arb", 9, 1, None),
+    ("hlt", 99, 0, None),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(i64),
+    Ident(String),
+    Str(String),
+    Sym(char),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num(i64),
+    Label(String),
+    Here,
+    Neg(Box<Expr>),
+    Bin(char, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, labels: &HashMap<String, i64>, here: i64) -> Result<i64, AsmErrorKind> {
+        match self {
+            Expr::Num(n) => Ok(*n),
+            Expr::Label(name) => labels
+                .get(name)
+                .copied()
+                .ok_or_else(|| AsmErrorKind::UndefinedLabel(name.clone())),
+            Expr::Here => Ok(here),
+            Expr::Neg(e) => e
+                .eval(labels, here)?
+                .checked_neg()
+                .ok_or(AsmErrorKind::Overflow),
+            Expr::Bin(op, a, b) => {
+                let a = a.eval(labels, here)?;
+                let b = b.eval(labels, here)?;
+
+                let val = match op {
+                    '+' => a.checked_add(b),
+                    '-' => a.checked_sub(b),
+                    '*' => a.checked_mul(b),
+                    _ if b == 0 => return Err(AsmErrorKind::DivisionByZero),
+                    _ => a.checked_div(b),
+                };
+
+                val.ok_or(AsmErrorKind::Overflow)
+            }
+        }
+    }
+}
+
+enum Item {
+    Instruction {
+        opcode: i64,
+        operands: Vec<(ParamMode, Expr)>,
+    },
+    Data(Vec<Expr>),
+}
+
+struct Line {
+    number: usize,
+    addr: i64,
+    item: Item,
+}
+
+pub fn assemble(source: &str) -> Result<Vec<i64>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut lines = vec![];
+    let mut addr = 0i64;
+
+    for (i, raw) in source.lines().enumerate() {
+        let number = i + 1;
+        let err = |kind| AsmError { line: number, kind };
+
+        let tokens = tokenize(raw).map_err(err)?;
+        let mut rest = &tokens[..];
+
+        while let [Token::Ident(name), Token::Sym(':'), tail @ ..] = rest {
+            if labels.insert(name.clone(), addr).is_some() {
+                return Err(err(AsmErrorKind::DuplicateLabel(name.clone())));
+            }
+            rest = tail;
+        }
+
+        let (mnemonic, rest) = match rest {
+            [] => continue,
+            [Token::Ident(mnemonic), rest @ ..] => (mnemonic.to_lowercase(), rest),
+            _ => {
+                return Err(err(AsmErrorKind::InvalidSyntax(
+                    "expected a label, mnemonic or directive".to_string(),
+                )))
+            }
+        };
+
+        let item = if mnemonic == "data" {
+            parse_data(rest).map_err(err)?
+        } else {
+            parse_instruction(&mnemonic, rest).map_err(err)?
+        };
+
+        let size = match &item {
+            Item::Instruction { operands, .. } => operands.len() + 1,
+            Item::Data(values) => values.len(),
+        };
+
+        lines.push(Line { number, addr, item });
+        addr += size as i64;
+    }
+
+    let mut out = Vec::with_capacity(addr as usize);
+    for line in lines {
+        let number = line.number;
+        let err = |kind| AsmError { line: number, kind };
+
+        match line.item {
+            Item::Instruction { opcode, operands } => {
+                let mut raw_op = opcode;
+                let mut mode_place = 100;
+                for (mode, _) in &operands {
+                    raw_op += mode_place * mode.digit();
+                    mode_place *= 10;
+                }
+
+                out.push(raw_op);
+                for (_, expr) in &operands {
+                    out.push(expr.eval(&labels, line.addr).map_err(err)?);
+                }
+            }
+            Item::Data(values) => {
+                for expr in &values {
+                    out.push(expr.eval(&labels, line.addr).map_err(err)?);
+                }
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+fn parse_instruction(mnemonic: &str, tokens: &[Token]) -> Result<Item, AsmErrorKind> {
+    let (_, opcode, arity, writes) = MNEMONICS
+        .iter()
+        .find(|(name, _, _, _)| *name == mnemonic)
+        .ok_or_else(|| AsmErrorKind::UnknownMnemonic(mnemonic.to_string()))?;
+
+    let operands = split_commas(tokens)
+        .into_iter()
+        .map(parse_operand)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if operands.len() != *arity {
+        return Err(AsmErrorKind::OperandCount {
+            mnemonic: mnemonic.to_string(),
+            expected: *arity,
+            found: operands.len(),
+        });
+    }
+
+    if let Some(idx) = writes {
+        if operands[*idx].0 == ParamMode::Immediate {
+            return Err(AsmErrorKind::ImmediateWrite(mnemonic.to_string()));
+        }
+    }
+
+    Ok(Item::Instruction {
+        opcode: *opcode,
+        operands,
+    })
+}
+
+fn parse_data(tokens: &[Token]) -> Result<Item, AsmErrorKind> {
+    let mut values = vec![];
+
+    for group in split_commas(tokens) {
+        match group {
+            [Token::Str(s)] => values.extend(s.chars().map(|c| Expr::Num(c as i64))),
+            _ => values.push(parse_expr(group)?),
+        }
+    }
+
+    Ok(Item::Data(values))
+}
+
+fn parse_operand(tokens: &[Token]) -> Result<(ParamMode, Expr), AsmErrorKind> {
+    match tokens {
+        [Token::Sym('['), inner @ .., Token::Sym(']')] => match inner {
+            [Token::Ident(rb), rest @ ..] if rb == "rb" => {
+                let mut tokens = vec![Token::Num(0)];
+                tokens.extend_from_slice(rest);
+                Ok((ParamMode::Relative, parse_expr(&tokens)?))
+            }
+            _ => Ok((ParamMode::Position, parse_expr(inner)?)),
+        },
+        _ => Ok((ParamMode::Immediate, parse_expr(tokens)?)),
+    }
+}
+
+fn split_commas(tokens: &[Token]) -> Vec<&[Token]> {
+    if tokens.is_empty() {
+        return vec![];
+    }
+
+    tokens.split(|t| *t == Token::Sym(',')).collect()
+}
+
+fn parse_expr(tokens: &[Token]) -> Result<Expr, AsmErrorKind> {
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.expr()?;
+
+    match parser.tokens.get(parser.pos) {
+        None => Ok(expr),
+        Some(t) => Err(AsmErrorKind::InvalidSyntax(format!(
+            "unexpected {} in expression",
+            describe(t)
+        ))),
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Num(n) => format!("number {}", n),
+        Token::Ident(s) => format!("identifier {:?}", s),
+        Token::Str(s) => format!("string {:?}", s),
+        Token::Sym(c) => format!("{:?}", c),
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek_sym(&self) -> Option<char> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Sym(c)) => Some(*c),
+            _ => None,
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, AsmErrorKind> {
+        let mut lhs = self.term()?;
+        while let Some(op @ '+') | Some(op @ '-') = self.peek_sym() {
+            self.pos += 1;
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.term()?));
+        }
+
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> Result<Expr, AsmErrorKind> {
+        let mut lhs = self.unary()?;
+        while let Some(op @ '*') | Some(op @ '/') = self.peek_sym() {
+            self.pos += 1;
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, AsmErrorKind> {
+        match self.peek_sym() {
+            Some('-') => {
+                self.pos += 1;
+                Ok(Expr::Neg(Box::new(self.unary()?)))
+            }
+            Some('+') => {
+                self.pos += 1;
+                self.unary()
+            }
+            _ => self.atom(),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, AsmErrorKind> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .ok_or_else(|| AsmErrorKind::InvalidSyntax("expected an expression".to_string()))?;
+        self.pos += 1;
+
+        match token {
+            Token::Num(n) => Ok(Expr::Num(*n)),
+            Token::Ident(name) if name == "rb" => Err(AsmErrorKind::InvalidSyntax(
+                "rb may only start a relative operand".to_string(),
+            )),
+            Token::Ident(name) => Ok(Expr::Label(name.clone())),
+            Token::Sym('$') => Ok(Expr::Here),
+            Token::Sym('(') => {
+                let expr = self.expr()?;
+                if self.peek_sym() != Some(')') {
+                    return Err(AsmErrorKind::InvalidSyntax("expected ')'".to_string()));
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            t => Err(AsmErrorKind::InvalidSyntax(format!(
+                "unexpected {} in expression",
+                describe(t)
+            ))),
+        }
+    }
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>, AsmErrorKind> {
+    let mut tokens = vec![];
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            ';' => break,
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            c if c.is_ascii_digit() => {
+                let mut raw = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_ascii_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    raw.push(c);
+                    chars.next();
+                }
+
+                let digits = raw.replace('_', "");
+                let parsed = if digits.starts_with("0x") || digits.starts_with("0X") {
+                    i64::from_str_radix(&digits[2..], 16)
+                } else {
+                    digits.parse::<i64>()
+                };
+
+                tokens.push(Token::Num(
+                    parsed.map_err(|_| AsmErrorKind::InvalidNumber(raw))?,
+                ));
+            }
+            c if c.is_alphabetic() || c == '_' || c == '.' => {
+                let mut ident = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_alphanumeric() && c != '_' && c != '.' {
+                        break;
+                    }
+                    ident.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            '\'' | '"' => {
+                chars.next();
+
+                let mut literal = String::new();
+                loop {
+                    let c = match chars.next() {
+                        Some('\\') => match chars.next() {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some('0') => '\0',
+                            Some(c) => c,
+                            None => return Err(AsmErrorKind::UnterminatedLiteral),
+                        },
+                        Some(end) if end == c => break,
+                        Some(c) => c,
+                        None => return Err(AsmErrorKind::UnterminatedLiteral),
+                    };
+                    literal.push(c);
+                }
+
+                if c == '"' {
+                    tokens.push(Token::Str(literal));
+                } else {
+                    let mut it = literal.chars();
+                    match (it.next(), it.next()) {
+                        (Some(ch), None) => tokens.push(Token::Num(ch as i64)),
+                        _ => {
+                            return Err(AsmErrorKind::InvalidSyntax(
+                                "character literals must contain exactly one character".to_string(),
+                            ))
+                        }
+                    }
+                }
+            }
+            '+' | '-' | '*' | '/' | '(' | ')' | '[' | ']' | ',' | ':' | '$' => {
+                tokens.push(Token::Sym(c));
+                chars.next();
+            }
+            c => return Err(AsmErrorKind::UnexpectedChar(c)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{read_input, Computer};
+
+    fn run(program: &[i64], inputs: &[i64]) -> Vec<i64> {
+        let mut computer = Computer::new(program.to_vec());
+        for input in inputs {
+            computer.push_input(*input);
+        }
+        computer.run_to_halt().unwrap()
+    }
+
+    #[test]
+    fn encodes_modes() {
+        let program = assemble("add [4], 3, [rb - 2]\nmul [rb], [rb+7], [0]\nhlt").unwrap();
+        assert_eq!(vec![21001, 4, 3, -2, 2202, 0, 7, 0, 99], program);
+    }
+
+    #[test]
+    fn relative_base_quine() {
+        let source = "
+            start:  arb 1
+                    out [rb - 1]
+                    add [100], 1, [100]
+                    eq [100], 16, [101]
+                    jf [101], start
+                    hlt
+        ";
+
+        let expected =
+            read_input(&b"109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99"[..]);
+        assert_eq!(expected, assemble(source).unwrap());
+    }
+
+    #[test]
+    fn labels_data_and_expressions() {
+        let source = "
+                    in [n]
+            loop:   out [n]             ; forward and backward references
+                    add [n], -1, [n]
+                    jt [n], loop
+                    out [msg + 1]
+                    out (end - msg) * 2
+                    hlt
+            n:      data 0
+            msg:    data \"hi\", '!'
+            end:
+        ";
+
+        let program = assemble(source).unwrap();
+        assert_eq!(vec![3, 2, 1, 'i' as i64, 6], run(&program, &[3]));
+    }
+
+    #[test]
+    fn current_address() {
+        let program = assemble("data 7\njt 1, $").unwrap();
+        assert_eq!(vec![7, 1105, 1, 1], program);
+    }
+
+    #[test]
+    fn errors_report_line_numbers() {
+        let err = |source: &str| assemble(source).unwrap_err();
+
+        assert_eq!(
+            AsmError {
+                line: 2,
+                kind: AsmErrorKind::UnknownMnemonic("jmp".to_string())
+            },
+            err("hlt\njmp 0")
+        );
+        assert_eq!(
+            AsmError {
+                line: 1,
+                kind: AsmErrorKind::ImmediateWrite("add".to_string())
+            },
+            err("add 1, 2, 3")
+        );
+        assert_eq!(
+            AsmError {
+                line: 3,
+                kind: AsmErrorKind::UndefinedLabel("nowhere".to_string())
+            },
+            err("\n\njt 1, nowhere")
+        );
+        assert_eq!(
+            AsmError {
+                line: 2,
+                kind: AsmErrorKind::DuplicateLabel("a".to_string())
+            },
+            err("a: hlt\na: hlt")
+        );
+        assert_eq!(
+            AsmError {
+                line: 1,
+                kind: AsmErrorKind::OperandCount {
+                    mnemonic: "out".to_string(),
+                    expected: 1,
+                    found: 2
+                }
+            },
+            err("out 1, 2")
+        );
+        assert_eq!(
+            AsmError {
+                line: 1,
+                kind: AsmErrorKind::DivisionByZero
+            },
+            err("data 1 / (2 - 2)")
+        );
+        assert_eq!(
+            "line 1: unexpected character '%'",
+            err("out 5 % 2").to_string()
+        );
+    }
+}
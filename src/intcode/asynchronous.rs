@@ -0,0 +1,303 @@
+//! Async execution, so many machines can share a single thread.
+//!
+//! `Computer::run_async` awaits a `Stream` whenever it needs input and sends its output to a
+//! `Sink`, yielding to the executor instead of blocking a thread. `ring` builds a day 7 style
+//! feedback loop out of such machines and runs it on a `LocalPool` on the calling thread, so a
+//! loop of hundreds of machines costs hundreds of small tasks rather than hundreds of threads.
+//! Machines yield to the executor every so often even when they never wait, and like
+//! `Network::run`, `ring` stops machines at a step limit and reports a loop that stalls instead
+//! of hanging.
+
+use std::cell::RefCell;
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use futures::channel::mpsc;
+use futures::executor::LocalPool;
+use futures::task::LocalSpawnExt;
+use futures::{future, sink, Sink, SinkExt, Stream, StreamExt};
+
+use super::budget::{Budget, Outcome};
+use super::network::{Deadlock, NodeState, Report, Waiting, DEFAULT_STEP_LIMIT};
+use super::{Computer, Result, ReturnMode};
+
+/// Steps a machine takes between giving other tasks a turn.
+const YIELD_INTERVAL: u64 = 10_000;
+
+impl Computer {
+    /// Runs the program to completion, awaiting `input` whenever the queued input runs out and
+    /// sending every output to `output`. Reports `InputExhausted` if `input` ends first. Values
+    /// the sink rejects, e.g. because its receiver is gone, are discarded.
+    pub async fn run_async<I, O>(&mut self, input: I, output: O) -> Result<()>
+    where
+        I: Stream<Item = i64> + Unpin,
+        O: Sink<i64> + Unpin,
+    {
+        self.run_async_budgeted(input, output, &mut Budget::new())
+            .await
+            .map(|_| ())
+    }
+
+    /// Like `run_async`, but gives up once `budget` is exhausted, leaving the machine on the next
+    /// instruction to execute. Returns `Returned(Halt)` once the program halts.
+    pub async fn run_async_budgeted<I, O>(
+        &mut self,
+        mut input: I,
+        mut output: O,
+        budget: &mut Budget,
+    ) -> Result<Outcome>
+    where
+        I: Stream<Item = i64> + Unpin,
+        O: Sink<i64> + Unpin,
+    {
+        let mut steps = 0;
+        loop {
+            if let Some(exhausted) = budget.charge() {
+                return Ok(Outcome::BudgetExhausted(exhausted));
+            }
+
+            match self.step()? {
+                None => {}
+                Some(ReturnMode::Output(val)) => {
+                    let _ = output.send(val).await;
+                }
+                Some(ReturnMode::NeedsInput) => match input.next().await {
+                    Some(val) => self.push_input(val),
+                    None => return Err(self.input_exhausted()),
+                },
+                Some(ReturnMode::Halt) => return Ok(Outcome::Returned(ReturnMode::Halt)),
+            }
+
+            // A sink that is always ready never suspends, so take turns explicitly.
+            steps += 1;
+            if steps % YIELD_INTERVAL == 0 {
+                YieldNow(false).await;
+            }
+        }
+    }
+}
+
+/// Suspends once, waking itself straight away so the task is polled again after the others.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Runs one copy of `program` per entry of `seeds`, each starting with its seed values queued,
+/// with every machine's output feeding the next one's input and the last feeding the first.
+/// Runs until every machine has halted, faulted or taken `DEFAULT_STEP_LIMIT` steps, or none can
+/// make progress, and reports the same way a `Network` ring would.
+pub fn ring(program: &[i64], seeds: &[Vec<i64>]) -> Report {
+    ring_with_step_limit(program, seeds, DEFAULT_STEP_LIMIT)
+}
+
+/// Like `ring`, but stops each machine once it has taken `steps` steps.
+pub fn ring_with_step_limit(program: &[i64], seeds: &[Vec<i64>], steps: u64) -> Report {
+    let count = seeds.len();
+    let base = Computer::new(program.to_vec());
+    let homes: Vec<_> = (0..count).map(|_| Rc::new(RefCell::new(None))).collect();
+    let states = Rc::new(RefCell::new(vec![NodeState::Runnable; count]));
+    let outputs: Vec<_> = (0..count).map(|_| Rc::new(RefCell::new(vec![]))).collect();
+
+    // The senders stay alive until the pool has stalled, so a machine whose source finished
+    // keeps waiting, as it would in a `Network`, rather than seeing its input end.
+    let (senders, receivers): (Vec<_>, Vec<_>) = (0..count).map(|_| mpsc::unbounded()).unzip();
+
+    let mut pool = LocalPool::new();
+    let spawner = pool.spawner();
+    for (id, (input, seed)) in receivers.into_iter().zip(seeds).enumerate() {
+        let mut computer = base.fork();
+        for val in seed {
+            computer.push_input(*val);
+        }
+        let mut lease = Lease {
+            computer: Some(computer),
+            home: Rc::clone(&homes[id]),
+        };
+        let states = Rc::clone(&states);
+        let log = Rc::clone(&outputs[id]);
+        // Outputs for a machine that has finished are dropped, as in a `Network`.
+        let target = senders[(id + 1) % count].clone();
+        let output = Box::pin(sink::unfold(target, move |target, val| {
+            log.borrow_mut().push(val);
+            let _ = target.unbounded_send(val);
+            future::ok::<_, Infallible>(target)
+        }));
+
+        let machine = async move {
+            let computer = lease.computer.as_mut().unwrap();
+            let mut budget = Budget::new().steps(steps);
+            let result = computer
+                .run_async_budgeted(input, output, &mut budget)
+                .await;
+            states.borrow_mut()[id] = match result {
+                Ok(Outcome::Returned(_)) => NodeState::Halted,
+                Ok(Outcome::BudgetExhausted(_)) => NodeState::OutOfSteps {
+                    inst_ptr: computer.inst_ptr(),
+                },
+                Err(e) => NodeState::Faulted(e),
+            };
+        };
+        spawner
+            .spawn_local(machine)
+            .expect("the pool accepts tasks until it is dropped");
+    }
+
+    pool.run_until_stalled();
+    // Dropping the pool drops the machines still waiting for input, handing back their computers.
+    drop(pool);
+
+    let states: Vec<_> = states
+        .borrow()
+        .iter()
+        .zip(&homes)
+        .map(|(state, home)| match (state, &*home.borrow()) {
+            (NodeState::Runnable, Some(computer)) => NodeState::Waiting {
+                inst_ptr: computer.inst_ptr(),
+            },
+            (state, _) => *state,
+        })
+        .collect();
+
+    let waiting: Vec<_> = states
+        .iter()
+        .enumerate()
+        .filter_map(|(id, state)| match state {
+            NodeState::Waiting { inst_ptr } => {
+                let source = (id + count - 1) % count;
+                Some(Waiting {
+                    node: id,
+                    inst_ptr: *inst_ptr,
+                    sources: vec![(source, states[source])],
+                })
+            }
+            _ => None,
+        })
+        .collect();
+
+    Report {
+        states,
+        outputs: outputs.iter().map(|log| log.borrow().clone()).collect(),
+        deadlock: if waiting.is_empty() {
+            None
+        } else {
+            Some(Deadlock { waiting })
+        },
+    }
+}
+
+/// A machine's computer, handed back to `ring` once its task finishes or is dropped.
+struct Lease {
+    computer: Option<Computer>,
+    home: Rc<RefCell<Option<Computer>>>,
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        *self.home.borrow_mut() = self.computer.take();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::network::Network;
+    use crate::intcode::{read_input, IntcodeError};
+    use crate::util;
+    use futures::executor::block_on;
+    use futures::stream;
+
+    // Doubles every input until it reads a zero.
+    const DOUBLER: &[u8] = b"3,20,1006,20,14,1002,20,2,20,4,20,1105,1,0,99";
+
+    fn network_ring(program: &[i64], seeds: &[Vec<i64>], steps: u64) -> Report {
+        let mut network = Network::new();
+        network.step_limit(steps);
+        let nodes = network.add_nodes(program, seeds.len());
+        network.ring(&nodes);
+        for (node, seed) in nodes.iter().zip(seeds) {
+            for val in seed {
+                network.seed(*node, *val);
+            }
+        }
+        network.run()
+    }
+
+    #[test]
+    fn streams_in_and_sinks_out() {
+        let mut computer = Computer::new(read_input(DOUBLER));
+        let mut outputs = vec![];
+        block_on(computer.run_async(stream::iter(vec![1, 7, 0]), &mut outputs)).unwrap();
+        assert_eq!(vec![2, 14], outputs);
+
+        let mut computer = Computer::new(read_input(DOUBLER));
+        assert!(matches!(
+            block_on(computer.run_async(stream::iter(vec![5]), &mut outputs)),
+            Err(IntcodeError::InputExhausted { inst_ptr: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn yields_while_running() {
+        let mut computer = Computer::new(read_input(&b"104,1,1105,1,0"[..]));
+        let mut outputs = vec![];
+        let run = Box::pin(computer.run_async(stream::empty(), &mut outputs));
+        block_on(future::select(run, future::ready(())));
+        assert_eq!(YIELD_INTERVAL as usize / 2, outputs.len());
+    }
+
+    #[test]
+    fn runs_a_large_feedback_ring() {
+        let program = read_input(&util::read_input_file("day7.txt")[..]);
+        let mut seeds: Vec<_> = (0..64).map(|id| vec![5 + id % 5]).collect();
+        seeds[0].push(0);
+
+        let report = ring(&program, &seeds);
+        assert!(report.all_halted(), "{:?}", report.deadlock);
+        assert_eq!(network_ring(&program, &seeds, DEFAULT_STEP_LIMIT), report);
+    }
+
+    #[test]
+    fn matches_the_day7_answer() {
+        let program = read_input(&util::read_input_file("day7.txt")[..]);
+        let seeds = vec![vec![6, 0], vec![7], vec![9], vec![8], vec![5]];
+        assert_eq!(Some(61379886), ring(&program, &seeds).last_output(4));
+    }
+
+    #[test]
+    fn reports_a_stalled_ring() {
+        // Adds one to every input, forever, but nobody sends the first value.
+        let program = read_input(&b"3,9,101,1,9,9,4,9,1105,1,0"[..]);
+        let seeds = vec![vec![]; 3];
+
+        let report = ring(&program, &seeds);
+        let deadlock = report.deadlock.as_ref().unwrap();
+        assert_eq!(3, deadlock.waiting.len());
+        assert_eq!(network_ring(&program, &seeds, DEFAULT_STEP_LIMIT), report);
+    }
+
+    #[test]
+    fn stops_machines_that_output_forever() {
+        let program = read_input(&b"104,1,1105,1,0"[..]);
+        let seeds = vec![vec![]; 3];
+
+        let report = ring_with_step_limit(&program, &seeds, 100_000);
+        assert_eq!(vec![0, 1, 2], report.out_of_steps());
+        assert_eq!(50_000, report.outputs[2].len());
+        assert_eq!(network_ring(&program, &seeds, 100_000), report);
+    }
+}
@@ -0,0 +1,199 @@
+//! Limits on how long a run may go on, so a program stuck in a loop can't wedge its caller.
+//!
+//! A `Budget` caps the number of instructions executed, the wall-clock time spent, or both, and
+//! can carry a `CancelToken` that another thread flips to stop the run. Exhausting a budget
+//! leaves the machine on the next instruction to execute, so it can be resumed with a fresh or
+//! extended budget as if nothing happened.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::ReturnMode;
+
+/// The clock is only read once per this many instructions.
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// A shared flag for cancelling runs from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stops every run using this token before its next instruction.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Which limit stopped a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exhausted {
+    Steps,
+    Time,
+    Cancelled,
+}
+
+/// How a budgeted run stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome<W = i64> {
+    Returned(ReturnMode<W>),
+    BudgetExhausted(Exhausted),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    steps: Option<u64>,
+    deadline: Option<Instant>,
+    cancel: Option<CancelToken>,
+    used: u64,
+}
+
+impl Budget {
+    /// A budget with no limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows at most `steps` instructions.
+    pub fn steps(mut self, steps: u64) -> Self {
+        self.steps = Some(steps);
+        self
+    }
+
+    /// Allows running for `limit` from now. The clock is checked every 1024 instructions, so a
+    /// run may overshoot slightly.
+    pub fn time(self, limit: Duration) -> Self {
+        self.deadline(Instant::now() + limit)
+    }
+
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Instructions executed under this budget so far.
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    pub fn remaining_steps(&self) -> Option<u64> {
+        self.steps.map(|steps| steps.saturating_sub(self.used))
+    }
+
+    /// Raises the step limit by `steps`, e.g. to resume a run that used it up.
+    pub fn add_steps(&mut self, steps: u64) {
+        if let Some(limit) = &mut self.steps {
+            *limit = limit.saturating_add(steps);
+        }
+    }
+
+    /// Charges one instruction, or reports which limit is already used up.
+    pub(crate) fn charge(&mut self) -> Option<Exhausted> {
+        if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+            return Some(Exhausted::Cancelled);
+        }
+
+        if self.remaining_steps() == Some(0) {
+            return Some(Exhausted::Steps);
+        }
+
+        if self.used.is_multiple_of(CLOCK_INTERVAL)
+            && self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Some(Exhausted::Time);
+        }
+
+        self.used += 1;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{read_input, Computer};
+    use std::thread;
+
+    const SPIN: &[u8] = b"1105,1,0";
+
+    #[test]
+    fn step_budget_is_resumable() {
+        // Outputs 5, 4, ..., 1 then halts.
+        let program = read_input(&b"3,12,4,12,1001,12,-1,12,1005,12,2,99,0"[..]);
+        let mut unbounded = Computer::new(program.clone());
+        unbounded.push_input(5);
+        let expected = unbounded.run_to_halt().unwrap();
+
+        let mut computer = Computer::new(program);
+        computer.push_input(5);
+        let mut budget = Budget::new().steps(2);
+        let mut outputs = vec![];
+        let mut exhausted = 0;
+        loop {
+            match computer.run_budgeted(&mut budget).unwrap() {
+                Outcome::Returned(ReturnMode::Output(val)) => outputs.push(val),
+                Outcome::Returned(ReturnMode::Halt) => break,
+                Outcome::Returned(ReturnMode::NeedsInput) => panic!("unexpected input request"),
+                Outcome::BudgetExhausted(Exhausted::Steps) => {
+                    exhausted += 1;
+                    budget.add_steps(2);
+                }
+                Outcome::BudgetExhausted(e) => panic!("unexpected {:?}", e),
+            }
+        }
+
+        assert_eq!(expected, outputs);
+        assert!(exhausted > 0);
+    }
+
+    #[test]
+    fn infinite_loops_run_out_of_steps_and_time() {
+        let mut computer = Computer::new(read_input(SPIN));
+        let mut budget = Budget::new().steps(1000);
+        assert_eq!(
+            Outcome::BudgetExhausted(Exhausted::Steps),
+            computer.run_budgeted(&mut budget).unwrap()
+        );
+        assert_eq!(1000, budget.used());
+        assert_eq!(0, computer.inst_ptr());
+
+        let mut budget = Budget::new().time(Duration::from_millis(20));
+        assert_eq!(
+            Outcome::BudgetExhausted(Exhausted::Time),
+            computer.run_budgeted(&mut budget).unwrap()
+        );
+    }
+
+    #[test]
+    fn cancelled_from_another_thread() {
+        let token = CancelToken::new();
+        let mut budget = Budget::new().cancel_token(token.clone());
+
+        let handle = thread::spawn(move || {
+            let mut computer = Computer::new(read_input(SPIN));
+            computer.run_budgeted(&mut budget).unwrap()
+        });
+        thread::sleep(Duration::from_millis(10));
+        token.cancel();
+
+        assert_eq!(
+            Outcome::BudgetExhausted(Exhausted::Cancelled),
+            handle.join().unwrap()
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! Per-address cache of decoded instructions for the interpreter.
+//!
+//! The cache covers the addresses the program occupied when it was first used and is shared
+//! copy-on-write between forks, like memory pages. Any write landing inside a cached
+//! instruction evicts it, so self-modifying programs are decoded again after each change.
+//!
+//! Setting up the cache costs about as much as decoding the whole program once, so it is only
+//! built after a machine has decoded more instructions than its program is long. Short runs,
+//! like a single amplifier pass, never pay for it.
+
+use std::fmt;
+use std::sync::Arc;
+
+use super::word::Word;
+use super::Op;
+
+/// Longest program whose instructions are cached; anything past this is decoded every time.
+const MAX_CACHED: usize = 1 << 20;
+
+/// The most words any instruction spans.
+const MAX_OP_SIZE: usize = 4;
+
+#[derive(Clone)]
+pub(crate) struct DecodeCache<W> {
+    ops: Arc<Vec<Option<Op<W>>>>,
+    misses: usize,
+}
+
+impl<W: Word> DecodeCache<W> {
+    pub(crate) fn get(&self, addr: usize) -> Option<Op<W>> {
+        self.ops.get(addr).cloned().flatten()
+    }
+
+    /// Caches `op` at `addr`. Once the cache is warranted it is sized to `memory_len`.
+    pub(crate) fn insert(&mut self, addr: usize, op: Op<W>, memory_len: usize) {
+        if self.ops.is_empty() {
+            self.misses += 1;
+            if self.misses <= memory_len {
+                return;
+            }
+            self.ops = Arc::new(vec![None; memory_len.min(MAX_CACHED)]);
+        }
+
+        if addr < self.ops.len() {
+            Arc::make_mut(&mut self.ops)[addr] = Some(op);
+        }
+    }
+
+    /// Evicts any cached instruction covering `addr`.
+    pub(crate) fn invalidate(&mut self, addr: usize) {
+        if addr >= self.ops.len() + MAX_OP_SIZE {
+            return;
+        }
+
+        let start = addr.saturating_sub(MAX_OP_SIZE - 1);
+        let end = (addr + 1).min(self.ops.len());
+        for start in start..end {
+            if let Some(op) = &self.ops[start] {
+                if addr < start + op.size() {
+                    Arc::make_mut(&mut self.ops)[start] = None;
+                }
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl<W> Default for DecodeCache<W> {
+    fn default() -> Self {
+        Self {
+            ops: Arc::default(),
+            misses: 0,
+        }
+    }
+}
+
+impl<W> fmt::Debug for DecodeCache<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cached = self.ops.iter().filter(|op| op.is_some()).count();
+        f.debug_struct("DecodeCache")
+            .field("cached", &cached)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::ParamMode;
+
+    fn warm(memory_len: usize) -> DecodeCache<i64> {
+        let mut cache = DecodeCache::default();
+        for _ in 0..=memory_len {
+            cache.insert(0, Op::Halt, memory_len);
+        }
+        cache.invalidate(0);
+        cache
+    }
+
+    #[test]
+    fn built_after_enough_misses() {
+        let mut cache = DecodeCache::<i64>::default();
+        for _ in 0..4 {
+            cache.insert(1, Op::Halt, 4);
+        }
+        assert_eq!(None, cache.get(1));
+
+        cache.insert(1, Op::Halt, 4);
+        assert_eq!(Some(Op::Halt), cache.get(1));
+    }
+
+    #[test]
+    fn writes_evict_covering_instructions() {
+        let add = Op::Add(
+            (ParamMode::Position, 0),
+            (ParamMode::Position, 0),
+            (ParamMode::Position, 0),
+        );
+
+        let mut cache = warm(8);
+        cache.insert(0, add, 8);
+        cache.insert(4, Op::Halt, 8);
+
+        cache.invalidate(5);
+        assert_eq!(Some(add), cache.get(0));
+        assert_eq!(Some(Op::Halt), cache.get(4));
+
+        cache.invalidate(3);
+        assert_eq!(None, cache.get(0));
+        assert_eq!(Some(Op::Halt), cache.get(4));
+
+        cache.invalidate(4);
+        assert_eq!(None, cache.get(4));
+    }
+
+    #[test]
+    fn clones_share_until_changed() {
+        let mut cache = warm(4);
+        cache.insert(0, Op::Halt, 4);
+
+        let mut fork = cache.clone();
+        fork.invalidate(0);
+        assert_eq!(Some(Op::Halt), cache.get(0));
+        assert_eq!(None, fork.get(0));
+    }
+}
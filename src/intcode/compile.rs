@@ -0,0 +1,322 @@
+//! Closure-threaded execution of pre-decoded Intcode programs.
+//!
+//! `Compiled::new` decodes every statically reachable instruction once and turns it into a
+//! closure specialised for its opcode and parameter modes, so running the program never goes
+//! back through `Op::decode`. The compiled form is immutable and can be shared between any
+//! number of `CompiledComputer`s, including across threads.
+//!
+//! Each `CompiledComputer` watches for writes that land inside a compiled instruction. Once an
+//! instruction has been overwritten, that instruction is executed by the interpreter from then
+//! on, as is anything the static pass couldn't reach (code behind indirect jumps).
+
+use super::disasm::Disassembly;
+use super::{Computer, Op, ParamMode, ParamWithMode, Result, ReturnMode};
+
+type Exec = Box<dyn Fn(&mut Computer) -> Result<Option<ReturnMode>> + Send + Sync>;
+type Load = Box<dyn Fn(&Computer) -> Result<i64> + Send + Sync>;
+
+struct Thunk {
+    op: Op,
+    exec: Exec,
+}
+
+pub struct Compiled {
+    program: Vec<i64>,
+    thunks: Vec<Option<Thunk>>,
+    /// The start of the compiled instruction covering each program address.
+    owners: Vec<Option<usize>>,
+}
+
+impl Compiled {
+    pub fn new(program: &[i64]) -> Self {
+        let disassembly = Disassembly::new(program);
+        let mut thunks: Vec<_> = (0..program.len()).map(|_| None).collect();
+        let mut owners = vec![None; program.len()];
+
+        for (&addr, &op) in disassembly.instructions() {
+            for owner in &mut owners[addr..addr + op.size()] {
+                *owner = Some(addr);
+            }
+            thunks[addr] = Some(Thunk {
+                op,
+                exec: compile(addr, op),
+            });
+        }
+
+        Self {
+            program: program.to_vec(),
+            thunks,
+            owners,
+        }
+    }
+
+    pub fn program(&self) -> &[i64] {
+        &self.program
+    }
+
+    /// Number of instructions that were compiled.
+    pub fn instructions(&self) -> usize {
+        self.thunks.iter().filter(|t| t.is_some()).count()
+    }
+
+    /// A fresh machine running this program.
+    pub fn computer(&self) -> CompiledComputer<'_> {
+        CompiledComputer::new(self, Computer::new(self.program.clone()))
+    }
+}
+
+pub struct CompiledComputer<'a> {
+    code: &'a Compiled,
+    computer: Computer,
+    stale: Vec<bool>,
+    interpreted: u64,
+}
+
+impl<'a> CompiledComputer<'a> {
+    /// Runs `code` on an existing machine, which is assumed to hold the compiled program.
+    pub fn new(code: &'a Compiled, computer: Computer) -> Self {
+        Self {
+            code,
+            computer,
+            stale: vec![],
+            interpreted: 0,
+        }
+    }
+
+    pub fn computer(&self) -> &Computer {
+        &self.computer
+    }
+
+    pub fn into_computer(self) -> Computer {
+        self.computer
+    }
+
+    pub fn peek(&self, addr: usize) -> i64 {
+        self.computer.peek(addr)
+    }
+
+    pub fn poke(&mut self, addr: usize, val: i64) {
+        self.computer.poke(addr, val);
+        self.wrote(addr);
+    }
+
+    pub fn push_input(&mut self, val: i64) {
+        self.computer.push_input(val);
+    }
+
+    /// Whether the compiled instruction at `addr` has been overwritten.
+    pub fn is_stale(&self, addr: usize) -> bool {
+        self.stale.get(addr).copied().unwrap_or(false)
+    }
+
+    /// Number of instructions that had to be run by the interpreter.
+    pub fn interpreted(&self) -> u64 {
+        self.interpreted
+    }
+
+    /// Same contract as `Computer::run_program`.
+    pub fn run_program(&mut self) -> Result<ReturnMode> {
+        loop {
+            if let Some(mode) = self.step()? {
+                return Ok(mode);
+            }
+        }
+    }
+
+    /// Same contract as `Computer::run_to_halt`.
+    pub fn run_to_halt(&mut self) -> Result<Vec<i64>> {
+        let mut outputs = vec![];
+        loop {
+            match self.run_program()? {
+                ReturnMode::Output(x) => outputs.push(x),
+                ReturnMode::NeedsInput => return Err(self.computer.input_exhausted()),
+                ReturnMode::Halt => return Ok(outputs),
+            }
+        }
+    }
+
+    pub fn step(&mut self) -> Result<Option<ReturnMode>> {
+        let inst_ptr = self.computer.inst_ptr;
+        let thunk = match self.code.thunks.get(inst_ptr) {
+            Some(Some(thunk)) if !self.is_stale(inst_ptr) => Some(thunk),
+            _ => None,
+        };
+
+        let op = match thunk {
+            Some(thunk) => thunk.op,
+            None => self.computer.read_op()?,
+        };
+        let dest = op
+            .destination()
+            .and_then(|p| self.computer.param_address(&p));
+
+        let mode = match thunk {
+            Some(thunk) => (thunk.exec)(&mut self.computer)?,
+            None => {
+                self.interpreted += 1;
+                self.computer.step()?
+            }
+        };
+
+        if let (Some(addr), false) = (dest, mode == Some(ReturnMode::NeedsInput)) {
+            self.wrote(addr as usize);
+        }
+
+        Ok(mode)
+    }
+
+    fn wrote(&mut self, addr: usize) {
+        if let Some(Some(owner)) = self.code.owners.get(addr) {
+            if self.stale.is_empty() {
+                self.stale = vec![false; self.code.program.len()];
+            }
+            self.stale[*owner] = true;
+        }
+    }
+}
+
+fn load(param: ParamWithMode) -> Load {
+    match param {
+        (ParamMode::Immediate, val) => Box::new(move |_| Ok(val)),
+        _ => Box::new(move |c| c.read_param(&param)),
+    }
+}
+
+fn binary(
+    addr: usize,
+    a: ParamWithMode,
+    b: ParamWithMode,
+    out: ParamWithMode,
+    f: fn(i64, i64) -> i64,
+) -> Exec {
+    let (a, b) = (load(a), load(b));
+    let next = addr + 4;
+    Box::new(move |c| {
+        let val = f(a(c)?, b(c)?);
+        c.write_param(&out, val)?;
+        c.inst_ptr = next;
+        Ok(None)
+    })
+}
+
+fn jump(addr: usize, cond: ParamWithMode, target: ParamWithMode, when: bool) -> Exec {
+    let cond = load(cond);
+    let next = addr + 3;
+    Box::new(move |c| {
+        c.inst_ptr = if (cond(c)? != 0) == when {
+            c.jump_target(&target)?
+        } else {
+            next
+        };
+        Ok(None)
+    })
+}
+
+fn compile(addr: usize, op: Op) -> Exec {
+    let next = addr + op.size();
+
+    match op {
+        Op::Add(a, b, out) => binary(addr, a, b, out, i64::wrapping_add),
+        Op::Mul(a, b, out) => binary(addr, a, b, out, i64::wrapping_mul),
+        Op::LessThan(a, b, out) => binary(addr, a, b, out, |a, b| (a < b) as i64),
+        Op::Equals(a, b, out) => binary(addr, a, b, out, |a, b| (a == b) as i64),
+        Op::JumpIfTrue(cond, target) => jump(addr, cond, target, true),
+        Op::JumpIfFalse(cond, target) => jump(addr, cond, target, false),
+        Op::Input(out) => Box::new(move |c| {
+            let val = match c.inputs.front() {
+                Some(val) => *val,
+                None => return Ok(Some(ReturnMode::NeedsInput)),
+            };
+            c.write_param(&out, val)?;
+            c.inputs.pop_front();
+            c.inst_ptr = next;
+            Ok(None)
+        }),
+        Op::Output(a) => {
+            let a = load(a);
+            Box::new(move |c| {
+                let val = a(c)?;
+                c.inst_ptr = next;
+                Ok(Some(ReturnMode::Output(val)))
+            })
+        }
+        Op::ModifyRelativeBase(a) => {
+            let a = load(a);
+            Box::new(move |c| {
+                c.relative_base = c.relative_base.wrapping_add(a(c)?);
+                c.inst_ptr = next;
+                Ok(None)
+            })
+        }
+        Op::Halt => Box::new(|_| Ok(Some(ReturnMode::Halt))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::read_input;
+    use crate::util;
+
+    fn puzzle(name: &str) -> Vec<i64> {
+        read_input(&util::read_input_file(name)[..])
+    }
+
+    #[test]
+    fn matches_the_interpreter_on_boost() {
+        let program = puzzle("day9.txt");
+        let compiled = Compiled::new(&program);
+
+        for input in &[1, 2] {
+            let mut computer = compiled.computer();
+            computer.push_input(*input);
+
+            let mut interpreter = Computer::new(program.clone());
+            interpreter.push_input(*input);
+
+            assert_eq!(
+                interpreter.run_to_halt().unwrap(),
+                computer.run_to_halt().unwrap()
+            );
+            assert_eq!(interpreter.snapshot(), computer.computer().snapshot());
+        }
+    }
+
+    #[test]
+    fn patched_operands_fall_back() {
+        let program = puzzle("day2.txt");
+        let compiled = Compiled::new(&program);
+
+        let mut computer = compiled.computer();
+        computer.poke(1, 12);
+        computer.poke(2, 2);
+        assert!(computer.is_stale(0));
+        assert_eq!(ReturnMode::Halt, computer.run_program().unwrap());
+        assert_eq!(6327510, computer.peek(0));
+        assert_eq!(1, computer.interpreted());
+    }
+
+    #[test]
+    fn self_modifying_code_falls_back() {
+        // The add rewrites the output that follows it from `out 0` to `out 42`.
+        let program = vec![1101, 0, 42, 5, 104, 0, 99];
+        let compiled = Compiled::new(&program);
+        assert_eq!(3, compiled.instructions());
+
+        let mut computer = compiled.computer();
+        assert_eq!(vec![42], computer.run_to_halt().unwrap());
+        assert!(computer.is_stale(4));
+        assert_eq!(1, computer.interpreted());
+    }
+
+    #[test]
+    fn indirect_jumps_are_interpreted() {
+        // Jumps through address 9 to an output the static pass can't see.
+        let program = vec![1105, 1, 4, 99, 105, 1, 9, 99, 99, 10, 104, 7, 99];
+        let compiled = Compiled::new(&program);
+
+        let mut computer = compiled.computer();
+        assert_eq!(vec![7], computer.run_to_halt().unwrap());
+        assert_eq!(2, computer.interpreted());
+    }
+}
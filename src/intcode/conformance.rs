@@ -0,0 +1,454 @@
+//! A table-driven conformance suite for Intcode backends.
+//!
+//! `suite` holds a small program for every opcode and parameter mode combination that can run
+//! without faulting, plus hand-written cases for relative-mode writes, memory growth up to and
+//! past the limit, self-modifying code, jumps to the end of memory and each kind of fault. Every
+//! test states the outputs, final memory and ending it expects. Any `fuzz::Backend` can be
+//! checked against it, under the same memory limit and step budget the fuzzer uses.
+
+use std::fmt;
+
+use super::coverage::Form;
+use super::fuzz::{Backend, Case, End, Run, MEMORY_LIMIT};
+use super::{IntcodeError, Op, ParamMode};
+
+/// A program and the run it must produce.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Test {
+    pub name: String,
+    pub program: Vec<i64>,
+    pub inputs: Vec<i64>,
+    pub outputs: Vec<i64>,
+    /// The whole of memory once the program stops, including any it grew.
+    pub memory: Vec<i64>,
+    pub end: End,
+}
+
+impl Test {
+    /// A test expecting `program` to halt without output or writes.
+    pub fn new(name: impl Into<String>, program: &[i64]) -> Self {
+        Self {
+            name: name.into(),
+            program: program.to_vec(),
+            inputs: vec![],
+            outputs: vec![],
+            memory: program.to_vec(),
+            end: End::Halted,
+        }
+    }
+
+    pub fn inputs(mut self, inputs: &[i64]) -> Self {
+        self.inputs = inputs.to_vec();
+        self
+    }
+
+    pub fn outputs(mut self, outputs: &[i64]) -> Self {
+        self.outputs = outputs.to_vec();
+        self
+    }
+
+    /// Expects `val` at `addr` once the program stops, with memory grown to hold it.
+    pub fn writes(mut self, addr: usize, val: i64) -> Self {
+        if addr >= self.memory.len() {
+            self.memory.resize(addr + 1, 0);
+        }
+        self.memory[addr] = val;
+        self
+    }
+
+    pub fn ends(mut self, end: End) -> Self {
+        self.end = end;
+        self
+    }
+
+    pub fn faults(self, e: IntcodeError) -> Self {
+        self.ends(End::Fault(e))
+    }
+
+    pub fn case(&self) -> Case {
+        Case {
+            program: self.program.clone(),
+            inputs: self.inputs.clone(),
+        }
+    }
+
+    fn expected(&self) -> Run {
+        Run {
+            outputs: self.outputs.clone(),
+            memory: self.memory.clone(),
+            end: self.end,
+        }
+    }
+
+    /// Runs the test on `backend`, returning what it did instead if that was wrong.
+    pub fn check(&self, backend: &dyn Backend) -> Option<Mismatch> {
+        let run = backend.run(&self.case());
+        if run.as_ref() == Some(&self.expected()) {
+            return None;
+        }
+
+        Some(Mismatch {
+            test: self.clone(),
+            backend: backend.name().to_string(),
+            run,
+        })
+    }
+}
+
+/// A test a backend failed. `run` is `None` if the backend declined to run it.
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    pub test: Test,
+    pub backend: String,
+    pub run: Option<Run>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} failed {}", self.backend, self.test.name)?;
+        write!(f, "{}", self.test.case())?;
+        writeln!(f, "  expected: {:?}", self.test.expected())?;
+        match &self.run {
+            Some(run) => writeln!(f, "  got:      {:?}", run),
+            None => writeln!(f, "  got:      no run"),
+        }
+    }
+}
+
+/// Runs the whole suite on `backend`, returning every test it failed.
+pub fn check(backend: &dyn Backend) -> Vec<Mismatch> {
+    suite()
+        .iter()
+        .filter_map(|test| test.check(backend))
+        .collect()
+}
+
+/// Every test, one per form first and then the edge cases.
+pub fn suite() -> Vec<Test> {
+    let mut tests: Vec<_> = Form::all().into_iter().flat_map(form_tests).collect();
+    tests.extend(edge_cases());
+    tests
+}
+
+/// The relative base the form tests set up, past the end of each program so that relative
+/// parameters are negative offsets.
+const BASE: i64 = 20;
+
+/// The parameter that resolves to `val`, stored at `addr`, in `mode`.
+fn param(mode: ParamMode, addr: usize, val: i64) -> i64 {
+    match mode {
+        ParamMode::Position => addr as i64,
+        ParamMode::Immediate => val,
+        ParamMode::Relative => addr as i64 - BASE,
+    }
+}
+
+/// Tests running `form` once, with its parameters in their modes.
+fn form_tests(form: Form) -> Vec<Test> {
+    let op = form.op();
+    let modes: Vec<_> = op.params().iter().map(|(mode, _)| *mode).collect();
+    let name = form.to_string();
+    let raw = form.raw();
+
+    match op {
+        Op::Add(..) | Op::Mul(..) | Op::LessThan(..) | Op::Equals(..) => {
+            // arb BASE; op a, b, dest; out [dest]; hlt; a; b; dest
+            let (a, b) = (6, 7);
+            let result = match op {
+                Op::Add(..) => a + b,
+                Op::Mul(..) => a * b,
+                Op::LessThan(..) => (a < b) as i64,
+                _ => (a == b) as i64,
+            };
+            let program = [
+                109,
+                BASE,
+                raw,
+                param(modes[0], 9, a),
+                param(modes[1], 10, b),
+                param(modes[2], 11, 0),
+                4,
+                11,
+                99,
+                a,
+                b,
+                -1,
+            ];
+            vec![Test::new(name, &program)
+                .outputs(&[result])
+                .writes(11, result)]
+        }
+        Op::Input(_) => {
+            // arb BASE; in dest; out [dest]; hlt; dest
+            let program = [109, BASE, raw, param(modes[0], 7, 0), 4, 7, 99, -1];
+            vec![Test::new(name, &program)
+                .inputs(&[5])
+                .outputs(&[5])
+                .writes(7, 5)]
+        }
+        Op::Output(_) => {
+            // arb BASE; out a; hlt; a
+            let program = [109, BASE, raw, param(modes[0], 5, 6), 99, 6];
+            vec![Test::new(name, &program).outputs(&[6])]
+        }
+        Op::JumpIfTrue(..) | Op::JumpIfFalse(..) => {
+            // arb BASE; op cond, target; out 0; hlt; target: out 1; hlt; cond; target
+            let jumps_when_nonzero = matches!(op, Op::JumpIfTrue(..));
+            [true, false]
+                .iter()
+                .map(|&taken| {
+                    let cond = if taken == jumps_when_nonzero { 3 } else { 0 };
+                    let program = [
+                        109,
+                        BASE,
+                        raw,
+                        param(modes[0], 11, cond),
+                        param(modes[1], 12, 8),
+                        104,
+                        0,
+                        99,
+                        104,
+                        1,
+                        99,
+                        cond,
+                        8,
+                    ];
+                    let name = format!("{}, {}", name, if taken { "taken" } else { "not taken" });
+                    Test::new(name, &program).outputs(&[taken as i64])
+                })
+                .collect()
+        }
+        Op::ModifyRelativeBase(_) => {
+            // arb BASE; arb delta; out [rb + x - BASE - delta]; hlt; delta; x
+            let delta = -12;
+            let program = [
+                109,
+                BASE,
+                raw,
+                param(modes[0], 7, delta),
+                204,
+                8 - BASE - delta,
+                99,
+                delta,
+                77,
+            ];
+            vec![Test::new(name, &program).outputs(&[77])]
+        }
+        Op::Halt => vec![Test::new(name, &[99])],
+    }
+}
+
+fn edge_cases() -> Vec<Test> {
+    let last = MEMORY_LIMIT as i64 - 1;
+
+    vec![
+        // Relative writes.
+        Test::new(
+            "relative write below the base",
+            &[109, 10, 21101, 3, 4, -1, 4, 9, 99, 0],
+        )
+        .outputs(&[7])
+        .writes(9, 7),
+        Test::new(
+            "relative write with a zero offset",
+            &[109, 7, 21101, 3, 4, 0, 99, 0],
+        )
+        .writes(7, 7),
+        Test::new("relative input", &[109, 100, 203, -93, 4, 7, 99, 0])
+            .inputs(&[-8])
+            .outputs(&[-8])
+            .writes(7, -8),
+        Test::new("relative base moves down", &[109, 10, 109, -4, 204, 0, 99]).outputs(&[99]),
+        // Memory growth.
+        Test::new("reading past the end gives zero", &[4, 50, 204, 50, 99]).outputs(&[0, 0]),
+        Test::new(
+            "writing past the end grows memory",
+            &[1101, 1, 2, 9, 4, 9, 99],
+        )
+        .outputs(&[3])
+        .writes(9, 3),
+        Test::new(
+            "relative write grows memory",
+            &[109, 100, 21101, 1, 2, 5, 204, 5, 99],
+        )
+        .outputs(&[3])
+        .writes(105, 3),
+        Test::new("writing zero still grows memory", &[1101, 0, 0, 6, 99]).writes(6, 0),
+        Test::new("write to the last address", &[1101, 1, 2, last, 99]).writes(last as usize, 3),
+        Test::new("write past the memory limit", &[1101, 1, 2, last + 1, 99]).faults(
+            IntcodeError::AddressOutOfRange {
+                inst_ptr: 0,
+                opcode: 1101,
+                address: last + 1,
+                limit: MEMORY_LIMIT,
+            },
+        ),
+        Test::new("read from the last address", &[4, last, 99]).outputs(&[0]),
+        // Self-modifying code.
+        Test::new(
+            "writes an opcode before reaching it",
+            &[1101, 100, 4, 4, 0, 42, 99],
+        )
+        .outputs(&[42])
+        .writes(4, 104),
+        Test::new("patches the next operand", &[1101, 0, 7, 5, 104, 0, 99])
+            .outputs(&[7])
+            .writes(5, 7),
+        Test::new("overwrites its own opcode", &[1101, 4, 0, 0, 4, 0, 99])
+            .outputs(&[4])
+            .writes(0, 4),
+        Test::new(
+            "rewrites code that already ran",
+            // out 1; add [1], 1, [1]; lt [1], 4, [16]; jt [16], 0; hlt; flag
+            &[
+                104, 1, 1001, 1, 1, 1, 1007, 1, 4, 16, 1005, 16, 0, 99, 0, 0, 0,
+            ],
+        )
+        .outputs(&[1, 2, 3])
+        .writes(1, 4)
+        .writes(16, 0),
+        Test::new("turns data into a halt", &[1101, 90, 9, 7, 1106, 0, 7, 0]).writes(7, 99),
+        // Jumps to the end of memory.
+        Test::new("jump to a halt in the last word", &[1105, 1, 4, 0, 99]),
+        Test::new("jump to the end of memory", &[1105, 1, 3]).faults(IntcodeError::UnknownOpcode {
+            inst_ptr: 3,
+            opcode: 0,
+        }),
+        Test::new(
+            "jump to code written past the end",
+            &[1101, 0, 99, 10, 1105, 1, 10],
+        )
+        .writes(10, 99),
+        Test::new("jump past the memory limit", &[1105, 1, last + 1]).faults(
+            IntcodeError::AddressOutOfRange {
+                inst_ptr: 0,
+                opcode: 1105,
+                address: last + 1,
+                limit: MEMORY_LIMIT,
+            },
+        ),
+        Test::new("instruction running off the end", &[1105, 1, 3, 1101, 1]).faults(
+            IntcodeError::TruncatedInstruction {
+                inst_ptr: 3,
+                opcode: 1101,
+            },
+        ),
+        // Faults and other endings.
+        Test::new("negative jump target", &[1105, 1, -1]).faults(IntcodeError::NegativeAddress {
+            inst_ptr: 0,
+            opcode: 1105,
+            address: -1,
+        }),
+        Test::new("negative read", &[4, -1, 99]).faults(IntcodeError::NegativeAddress {
+            inst_ptr: 0,
+            opcode: 4,
+            address: -1,
+        }),
+        Test::new("negative relative write", &[109, 2, 22101, 0, 0, -3, 99]).faults(
+            IntcodeError::NegativeAddress {
+                inst_ptr: 2,
+                opcode: 22101,
+                address: -1,
+            },
+        ),
+        Test::new("write to an immediate", &[11101, 1, 2, 3, 99]).faults(
+            IntcodeError::WriteToImmediate {
+                inst_ptr: 0,
+                opcode: 11101,
+            },
+        ),
+        Test::new("unknown opcode", &[42, 99]).faults(IntcodeError::UnknownOpcode {
+            inst_ptr: 0,
+            opcode: 42,
+        }),
+        Test::new("unknown parameter mode", &[304, 0, 99]).faults(IntcodeError::UnknownParamMode {
+            inst_ptr: 0,
+            opcode: 304,
+            mode: 3,
+        }),
+        Test::new("blocks without input", &[104, 1, 3, 0, 99])
+            .outputs(&[1])
+            .ends(End::NeedsInput),
+        Test::new(
+            "input is consumed in order",
+            &[3, 9, 3, 10, 4, 10, 4, 9, 99],
+        )
+        .inputs(&[1, 2])
+        .outputs(&[2, 1])
+        .writes(9, 1)
+        .writes(10, 2),
+        Test::new("runs out of steps", &[1105, 1, 0]).ends(End::OutOfSteps),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::coverage::Coverage;
+    use crate::intcode::fuzz::{self, Interpreter};
+    use crate::intcode::Computer;
+
+    #[test]
+    fn every_backend_conforms() {
+        for backend in fuzz::backends() {
+            let mismatches: Vec<_> = check(&*backend).iter().map(|m| m.to_string()).collect();
+            assert!(
+                mismatches.is_empty(),
+                "{} failed:\n{}",
+                backend.name(),
+                mismatches.join("\n")
+            );
+        }
+    }
+
+    #[test]
+    fn exercises_every_form() {
+        let mut coverage = Coverage::new();
+        for test in suite() {
+            let mut computer = Computer::new(test.program.clone());
+            for val in &test.inputs {
+                computer.push_input(*val);
+            }
+            for _ in 0..100 {
+                match computer.step_traced(&mut coverage) {
+                    Ok(None) => {}
+                    Ok(Some(_)) | Err(_) => break,
+                }
+            }
+        }
+
+        assert_eq!(Vec::<Form>::new(), coverage.missing_forms());
+    }
+
+    /// Outputs one less than it should whenever it outputs an odd number.
+    struct OffByOne;
+
+    impl Backend for OffByOne {
+        fn name(&self) -> &str {
+            "off-by-one"
+        }
+
+        fn run(&self, case: &Case) -> Option<Run> {
+            let mut run = Interpreter::<i64>::new("paged", false).run(case)?;
+            for val in &mut run.outputs {
+                if *val % 2 != 0 {
+                    *val -= 1;
+                }
+            }
+            Some(run)
+        }
+    }
+
+    #[test]
+    fn catches_a_broken_backend() {
+        let mismatches = check(&OffByOne);
+        assert!(!mismatches.is_empty());
+        assert!(mismatches
+            .iter()
+            .all(|mismatch| mismatch.backend == "off-by-one"));
+
+        let names: Vec<_> = mismatches.iter().map(|m| m.test.name.as_str()).collect();
+        assert!(names.contains(&"1 (add pos,pos,pos)"));
+        assert!(!names.contains(&"2 (mul pos,pos,pos)"));
+    }
+}
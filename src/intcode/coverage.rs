@@ -0,0 +1,628 @@
+//! Coverage of a program's memory: which addresses ran as instructions, which were read as data
+//! and which were written, along with which branch directions and which opcode and parameter
+//! mode combinations were exercised.
+//!
+//! `Coverage` is a `Tracer`, so it can watch any number of runs. The results render as an
+//! annotated listing, as plain text, with terminal colours or as HTML, and as an lcov-like
+//! record in which line numbers are addresses.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write};
+
+use super::asm::MNEMONICS;
+use super::disasm::{Disassembly, DATA_PER_LINE};
+use super::trace::{TraceEvent, Tracer};
+use super::{Op, ParamMode};
+
+/// An opcode together with the modes of its parameters, identified by its canonical encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Form(i64);
+
+impl Form {
+    pub fn of(op: &Op) -> Self {
+        Form(op.encode()[0])
+    }
+
+    /// The encoded opcode, e.g. 1002 for a `mul` whose second parameter is immediate.
+    pub fn raw(self) -> i64 {
+        self.0
+    }
+
+    /// Every form that can execute without faulting, which rules out immediate destinations.
+    pub fn all() -> Vec<Form> {
+        let mut forms = vec![];
+        for &(_, opcode, arity, writes) in &MNEMONICS {
+            for combination in 0..3i64.pow(arity as u32) {
+                let mut raw = opcode;
+                let mut modes = combination;
+                let mut place = 100;
+                for param in 0..arity {
+                    let digit = modes % 3;
+                    if digit == ParamMode::Immediate.digit() && writes == Some(param) {
+                        raw = -1;
+                        break;
+                    }
+                    raw += digit * place;
+                    modes /= 3;
+                    place *= 10;
+                }
+
+                if raw >= 0 {
+                    forms.push(Form(raw));
+                }
+            }
+        }
+
+        forms.sort();
+        forms
+    }
+
+    pub(crate) fn op(self) -> Op {
+        Op::decode(0, &[self.0, 0, 0, 0]).unwrap()
+    }
+}
+
+impl fmt::Display for Form {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = self.op();
+        let modes: Vec<_> = op
+            .params()
+            .iter()
+            .map(|(mode, _)| match mode {
+                ParamMode::Position => "pos",
+                ParamMode::Immediate => "imm",
+                ParamMode::Relative => "rel",
+            })
+            .collect();
+
+        if modes.is_empty() {
+            write!(f, "{} ({})", self.0, op.mnemonic())
+        } else {
+            write!(f, "{} ({} {})", self.0, op.mnemonic(), modes.join(","))
+        }
+    }
+}
+
+/// How often a conditional jump went each way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Branch {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// How a listing is rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Plain,
+    /// ANSI terminal colours.
+    Ansi,
+    Html,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    executed: BTreeMap<usize, u64>,
+    ops: BTreeMap<usize, Op>,
+    read: BTreeSet<usize>,
+    written: BTreeSet<usize>,
+    branches: BTreeMap<usize, Branch>,
+    forms: BTreeMap<Form, u64>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Execution counts keyed by instruction address.
+    pub fn executed(&self) -> &BTreeMap<usize, u64> {
+        &self.executed
+    }
+
+    /// Addresses read as operands.
+    pub fn read(&self) -> &BTreeSet<usize> {
+        &self.read
+    }
+
+    pub fn written(&self) -> &BTreeSet<usize> {
+        &self.written
+    }
+
+    /// Conditional jumps keyed by address.
+    pub fn branches(&self) -> &BTreeMap<usize, Branch> {
+        &self.branches
+    }
+
+    /// Execution counts of each form that ran.
+    pub fn forms(&self) -> &BTreeMap<Form, u64> {
+        &self.forms
+    }
+
+    /// Every form from `Form::all` that never ran.
+    pub fn missing_forms(&self) -> Vec<Form> {
+        Form::all()
+            .into_iter()
+            .filter(|form| !self.forms.contains_key(form))
+            .collect()
+    }
+
+    /// The address of every instruction in `program`: those that ran, and those reachable
+    /// statically that didn't. An instruction that ran is listed as it was executed, so code
+    /// the program wrote for itself shows up too.
+    fn instructions(&self, program: &[i64]) -> BTreeMap<usize, Op> {
+        let mut instructions = Disassembly::new(program).instructions().clone();
+        instructions.retain(|addr, op| {
+            let end = *addr + op.size();
+            self.executed.range(*addr..end).next().is_none()
+        });
+        instructions.extend(self.ops.iter().map(|(addr, op)| (*addr, *op)));
+        instructions
+    }
+
+    /// `program` listed with every instruction and data word annotated with its coverage.
+    /// Instructions that never ran are marked `#####`.
+    pub fn listing(&self, program: &[i64], style: Style) -> String {
+        let lines = self.lines(program);
+
+        let mut out = String::new();
+        if style == Style::Html {
+            out.push_str(HTML_HEADER);
+        }
+
+        for line in lines {
+            let text = format!(
+                "{:>9} {} {:>6}: {}",
+                line.count, line.flags, line.addr, line.text
+            );
+            match style {
+                Style::Plain => out.push_str(&text),
+                Style::Ansi => {
+                    write!(out, "\x1b[{}m{}\x1b[0m", line.kind.ansi(), text).unwrap();
+                }
+                Style::Html => {
+                    write!(
+                        out,
+                        "<span class=\"{}\">{}</span>",
+                        line.kind.class(),
+                        escape_html(&text)
+                    )
+                    .unwrap();
+                }
+            }
+            out.push('\n');
+        }
+
+        if style == Style::Html {
+            out.push_str(HTML_FOOTER);
+        }
+        out
+    }
+
+    fn lines(&self, program: &[i64]) -> Vec<Line> {
+        let instructions = self.instructions(program);
+        let flags = |start: usize, end: usize, executed: bool| {
+            let touched = |set: &BTreeSet<usize>| set.range(start..end).next().is_some();
+            [
+                if executed { 'x' } else { '-' },
+                if touched(&self.read) { 'r' } else { '-' },
+                if touched(&self.written) { 'w' } else { '-' },
+            ]
+            .iter()
+            .collect::<String>()
+        };
+
+        let mut lines = vec![];
+        let mut addr = 0;
+        while addr < program.len() {
+            if let Some(op) = instructions.get(&addr) {
+                let count = self.executed.get(&addr).copied();
+                let end = addr + op.size();
+                lines.push(Line {
+                    kind: if count.is_some() {
+                        Kind::Executed
+                    } else {
+                        Kind::Missed
+                    },
+                    count: count.map_or("#####".to_string(), |n| n.to_string()),
+                    flags: flags(addr, end, count.is_some()),
+                    addr,
+                    text: op.to_string(),
+                });
+
+                // Instructions that overlap this one still get lines of their own.
+                addr = instructions
+                    .range(addr + 1..end)
+                    .next()
+                    .map_or(end, |(next, _)| *next);
+                continue;
+            }
+
+            let touched = self.read.contains(&addr) || self.written.contains(&addr);
+            let mut end = addr + 1;
+            if !touched {
+                while end < program.len()
+                    && end - addr < DATA_PER_LINE
+                    && !instructions.contains_key(&end)
+                    && !self.read.contains(&end)
+                    && !self.written.contains(&end)
+                {
+                    end += 1;
+                }
+            }
+
+            let values: Vec<_> = program[addr..end].iter().map(|v| v.to_string()).collect();
+            lines.push(Line {
+                kind: Kind::data(self.written.contains(&addr), self.read.contains(&addr)),
+                count: String::new(),
+                flags: flags(addr, end, false),
+                addr,
+                text: format!("data {}", values.join(", ")),
+            });
+            addr = end;
+        }
+
+        // Memory the program used beyond its own image, such as a stack.
+        let beyond: BTreeSet<_> = self
+            .read
+            .range(program.len()..)
+            .chain(self.written.range(program.len()..))
+            .copied()
+            .collect();
+        for addr in beyond {
+            lines.push(Line {
+                kind: Kind::data(self.written.contains(&addr), self.read.contains(&addr)),
+                count: String::new(),
+                flags: flags(addr, addr + 1, false),
+                addr,
+                text: "; past the end of the program".to_string(),
+            });
+        }
+
+        lines
+    }
+
+    /// An lcov tracefile record for `program` under the source name `name`, with addresses in
+    /// place of line numbers and a branch pair for every conditional jump.
+    pub fn to_lcov(&self, program: &[i64], name: &str) -> String {
+        let instructions = self.instructions(program);
+        let mut out = format!("TN:\nSF:{}\n", name);
+
+        let mut found = 0;
+        let mut hit = 0;
+        for addr in instructions.keys() {
+            let count = self.executed.get(addr).copied().unwrap_or(0);
+            writeln!(out, "DA:{},{}", addr, count).unwrap();
+            found += 1;
+            if count > 0 {
+                hit += 1;
+            }
+        }
+
+        let mut branches_found = 0;
+        let mut branches_hit = 0;
+        for (addr, op) in &instructions {
+            let conditional = match op {
+                Op::JumpIfTrue(cond, _) | Op::JumpIfFalse(cond, _) => {
+                    cond.0 != ParamMode::Immediate
+                }
+                _ => false,
+            };
+            if !conditional && !self.branches.contains_key(addr) {
+                continue;
+            }
+
+            let counts = match self.branches.get(addr) {
+                Some(branch) => [branch.taken, branch.not_taken]
+                    .iter()
+                    .map(|n| n.to_string())
+                    .collect(),
+                None => vec!["-".to_string(); 2],
+            };
+            for (idx, count) in counts.iter().enumerate() {
+                writeln!(out, "BRDA:{},0,{},{}", addr, idx, count).unwrap();
+                branches_found += 1;
+                if count != "-" && count != "0" {
+                    branches_hit += 1;
+                }
+            }
+        }
+
+        write!(
+            out,
+            "BRF:{}\nBRH:{}\nLF:{}\nLH:{}\nend_of_record\n",
+            branches_found, branches_hit, found, hit
+        )
+        .unwrap();
+        out
+    }
+}
+
+impl Tracer for Coverage {
+    fn trace(&mut self, event: &TraceEvent) {
+        *self.executed.entry(event.inst_ptr).or_insert(0) += 1;
+        self.ops.insert(event.inst_ptr, event.op);
+        *self.forms.entry(Form::of(&event.op)).or_insert(0) += 1;
+
+        self.read
+            .extend(event.operands.iter().filter_map(|operand| operand.address));
+        if let Some(write) = event.write {
+            self.written.insert(write.addr);
+        }
+
+        let jumps_when_nonzero = match event.op {
+            Op::JumpIfTrue(_, _) => true,
+            Op::JumpIfFalse(_, _) => false,
+            _ => return,
+        };
+        let branch = self.branches.entry(event.inst_ptr).or_default();
+        if (event.operands[0].value != 0) == jumps_when_nonzero {
+            branch.taken += 1;
+        } else {
+            branch.not_taken += 1;
+        }
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} instructions executed at {} addresses",
+            self.executed.values().sum::<u64>(),
+            self.executed.len()
+        )?;
+        writeln!(
+            f,
+            "{} addresses read, {} written",
+            self.read.len(),
+            self.written.len()
+        )?;
+
+        let directions = self
+            .branches
+            .values()
+            .map(|b| (b.taken > 0) as usize + (b.not_taken > 0) as usize)
+            .sum::<usize>();
+        writeln!(
+            f,
+            "{} of {} branch directions taken",
+            directions,
+            2 * self.branches.len()
+        )?;
+
+        let missing = self.missing_forms();
+        let total = Form::all().len();
+        writeln!(f, "{} of {} forms exercised", total - missing.len(), total)?;
+        if !missing.is_empty() {
+            writeln!(f, "\nnever exercised:")?;
+            for form in missing {
+                writeln!(f, "  {}", form)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Executed,
+    Missed,
+    Written,
+    Read,
+    Untouched,
+}
+
+impl Kind {
+    fn data(written: bool, read: bool) -> Self {
+        match (written, read) {
+            (true, _) => Kind::Written,
+            (false, true) => Kind::Read,
+            (false, false) => Kind::Untouched,
+        }
+    }
+
+    fn ansi(self) -> &'static str {
+        match self {
+            Kind::Executed => "32",
+            Kind::Missed => "31",
+            Kind::Written => "33",
+            Kind::Read => "36",
+            Kind::Untouched => "2",
+        }
+    }
+
+    fn class(self) -> &'static str {
+        match self {
+            Kind::Executed => "executed",
+            Kind::Missed => "missed",
+            Kind::Written => "written",
+            Kind::Read => "read",
+            Kind::Untouched => "untouched",
+        }
+    }
+}
+
+struct Line {
+    kind: Kind,
+    count: String,
+    flags: String,
+    addr: usize,
+    text: String,
+}
+
+const HTML_HEADER: &str = "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>Intcode coverage</title>
+<style>
+.executed { background: #dfd; }
+.missed { background: #fdd; }
+.written { background: #ffd; }
+.read { background: #dff; }
+.untouched { color: #888; }
+</style>
+</head>
+<body>
+<pre>
+";
+
+const HTML_FOOTER: &str = "</pre>
+</body>
+</html>
+";
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+    use crate::intcode::{read_input, Computer, ReturnMode};
+    use crate::util;
+
+    // Counts down from its input, but never reaches the error branch.
+    const COUNTDOWN: &str = "
+                in [n]
+                jf [n], error
+        loop:   out [n]
+                add [n], -1, [n]
+                jt [n], loop
+                hlt
+        error:  out -1
+                hlt
+        n:      data 0
+        spare:  data 7, 8
+    ";
+
+    fn countdown(from: i64) -> (Vec<i64>, Coverage) {
+        let program = assemble(COUNTDOWN).unwrap();
+        let mut computer = Computer::new(program.clone());
+        computer.push_input(from);
+
+        let mut coverage = Coverage::new();
+        while computer.run_traced(&mut coverage).unwrap() != ReturnMode::Halt {}
+        (program, coverage)
+    }
+
+    #[test]
+    fn records_accesses() {
+        let (_, coverage) = countdown(3);
+
+        assert_eq!(
+            vec![(0, 1), (2, 1), (5, 3), (7, 3), (11, 3), (14, 1)],
+            coverage
+                .executed()
+                .iter()
+                .map(|(a, n)| (*a, *n))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![18],
+            coverage.read().iter().copied().collect::<Vec<_>>()
+        );
+        assert_eq!(&coverage.read(), &coverage.written());
+        assert_eq!(
+            Some(&Branch {
+                taken: 2,
+                not_taken: 1
+            }),
+            coverage.branches().get(&11)
+        );
+        assert_eq!(
+            Some(&Branch {
+                taken: 0,
+                not_taken: 1
+            }),
+            coverage.branches().get(&2)
+        );
+        assert_eq!(Some(&3), coverage.forms().get(&Form(1001)));
+    }
+
+    #[test]
+    fn lists_every_form() {
+        let forms = Form::all();
+        // Four three-parameter ops with 18 usable mode combinations each, two jumps with 9,
+        // in with 2, out and arb with 3, and hlt.
+        assert_eq!(4 * 18 + 2 * 9 + 2 + 2 * 3 + 1, forms.len());
+        assert!(forms.contains(&Form(21101)));
+        assert!(!forms.contains(&Form(11101)));
+        assert!(!forms.contains(&Form(103)));
+        assert_eq!("1205 (jt rel,imm)", Form(1205).to_string());
+        assert_eq!("99 (hlt)", Form(99).to_string());
+
+        let (_, coverage) = countdown(3);
+        let missing = coverage.missing_forms();
+        assert_eq!(forms.len() - 6, missing.len());
+        assert!(missing.contains(&Form(104)));
+    }
+
+    #[test]
+    fn annotates_listing() {
+        let (program, coverage) = countdown(3);
+        let listing = coverage.listing(&program, Style::Plain);
+        let lines: Vec<_> = listing.lines().collect();
+        assert_eq!(
+            vec![
+                "        1 x--      0: in [18]",
+                "        1 x--      2: jf [18], 15",
+                "        3 x--      5: out [18]",
+                "        3 x--      7: add [18], -1, [18]",
+                "        3 x--     11: jt [18], 5",
+                "        1 x--     14: hlt",
+                "    ##### ---     15: out -1",
+                "    ##### ---     17: hlt",
+                "          -rw     18: data 0",
+                "          ---     19: data 7, 8",
+            ],
+            lines
+        );
+
+        let ansi = coverage.listing(&program, Style::Ansi);
+        assert!(ansi.contains("\x1b[31m    ##### ---     15: out -1\x1b[0m"));
+
+        let html = coverage.listing(&program, Style::Html);
+        assert!(html.contains("<span class=\"missed\">    ##### ---     17: hlt</span>"));
+        assert!(html.ends_with("</html>\n"));
+    }
+
+    #[test]
+    fn writes_lcov() {
+        let (program, coverage) = countdown(1);
+        assert_eq!(
+            "TN:\nSF:countdown\n\
+             DA:0,1\nDA:2,1\nDA:5,1\nDA:7,1\nDA:11,1\nDA:14,1\nDA:15,0\nDA:17,0\n\
+             BRDA:2,0,0,0\nBRDA:2,0,1,1\nBRDA:11,0,0,0\nBRDA:11,0,1,1\n\
+             BRF:4\nBRH:2\nLF:8\nLH:6\nend_of_record\n",
+            coverage.to_lcov(&program, "countdown")
+        );
+    }
+
+    #[test]
+    fn boost_self_test_leaves_forms_unexercised() {
+        let program = read_input(&util::read_input_file("day9.txt")[..]);
+        let mut computer = Computer::new(program.clone());
+        computer.push_input(1);
+
+        let mut coverage = Coverage::new();
+        let mut outputs = vec![];
+        loop {
+            match computer.run_traced(&mut coverage).unwrap() {
+                ReturnMode::Output(val) => outputs.push(val),
+                ReturnMode::Halt => break,
+                ReturnMode::NeedsInput => panic!("unexpected input request"),
+            }
+        }
+
+        // A single output means every self-test passed.
+        assert_eq!(1, outputs.len());
+        assert!(coverage.forms().len() > 10);
+        assert!(!coverage.missing_forms().is_empty());
+        assert!(coverage.to_string().contains("never exercised:"));
+    }
+}
@@ -0,0 +1,329 @@
+//! Breakpoint and watchpoint driven execution on top of `Computer::step_traced`, with an undo log
+//! so a session can also run backwards.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::reverse::{Entry, UndoLog};
+use super::{Computer, IntcodeError, Op, ReturnMode};
+
+/// How many instructions the debugger can step back over.
+const HISTORY_LIMIT: usize = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    fn covers(self, access: Access) -> bool {
+        self == Access::ReadWrite || self == access
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The instruction executed without hitting anything of interest.
+    Stepped,
+    Breakpoint(usize),
+    /// The instruction at `inst_ptr` touched a watched address.
+    Watchpoint {
+        inst_ptr: usize,
+        addr: usize,
+        access: Access,
+    },
+    NeedsInput,
+    Halted,
+    Fault(IntcodeError),
+}
+
+pub struct Debugger {
+    computer: Computer,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeMap<usize, Access>,
+    outputs: Vec<i64>,
+    history: UndoLog,
+}
+
+impl Debugger {
+    pub fn new(computer: Computer) -> Self {
+        Self {
+            computer,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            outputs: vec![],
+            history: UndoLog::with_limit(HISTORY_LIMIT),
+        }
+    }
+
+    pub fn computer(&self) -> &Computer {
+        &self.computer
+    }
+
+    /// Changes made through this aren't recorded. Stepping back undoes a write by restoring the
+    /// value the cell held before it, so stepping back over an earlier write to a cell that was
+    /// edited here brings back that old value and loses the edit.
+    pub fn computer_mut(&mut self) -> &mut Computer {
+        &mut self.computer
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<usize> {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, addr: usize) -> bool {
+        self.breakpoints.insert(addr)
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: usize) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn watchpoints(&self) -> &BTreeMap<usize, Access> {
+        &self.watchpoints
+    }
+
+    pub fn watch(&mut self, addr: usize, access: Access) {
+        self.watchpoints.insert(addr, access);
+    }
+
+    pub fn unwatch(&mut self, addr: usize) -> bool {
+        self.watchpoints.remove(&addr).is_some()
+    }
+
+    /// Every value the program has output so far.
+    pub fn outputs(&self) -> &[i64] {
+        &self.outputs
+    }
+
+    /// Executes a single instruction. Halting changes nothing, so it isn't recorded in the
+    /// history however often a halted program is stepped.
+    pub fn step(&mut self) -> StopReason {
+        let inst_ptr = self.computer.inst_ptr();
+        if let Ok(Op::Halt) = self.computer.op_at(inst_ptr) {
+            return StopReason::Halted;
+        }
+        let watched = self.watched_access();
+
+        match self.computer.step_traced(&mut self.history) {
+            Err(e) => return StopReason::Fault(e),
+            Ok(Some(ReturnMode::Halt)) => return StopReason::Halted,
+            Ok(Some(ReturnMode::NeedsInput)) => return StopReason::NeedsInput,
+            Ok(Some(ReturnMode::Output(val))) => self.outputs.push(val),
+            Ok(None) => {}
+        }
+
+        match watched {
+            Some((addr, access)) => StopReason::Watchpoint {
+                inst_ptr,
+                addr,
+                access,
+            },
+            None => StopReason::Stepped,
+        }
+    }
+
+    /// Runs until a breakpoint or watchpoint is hit, the program needs input, halts or faults.
+    /// A breakpoint on the current instruction is ignored so that execution can move past it.
+    pub fn cont(&mut self) -> StopReason {
+        loop {
+            match self.step() {
+                StopReason::Stepped => {}
+                reason => return reason,
+            }
+
+            let inst_ptr = self.computer.inst_ptr();
+            if self.breakpoints.contains(&inst_ptr) {
+                return StopReason::Breakpoint(inst_ptr);
+            }
+        }
+    }
+
+    /// How many instructions can be stepped back over.
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Undoes the last instruction, returning what it did, or `None` at the start of the history.
+    pub fn step_back(&mut self) -> Option<Entry> {
+        let entry = self.history.undo(&mut self.computer)?;
+        if entry.output.is_some() {
+            self.outputs.pop();
+        }
+        Some(entry)
+    }
+
+    /// Runs backwards until just before the last instruction that wrote `addr`, returning it, or
+    /// to the start of the history if nothing recorded wrote there.
+    pub fn run_back_to_write(&mut self, addr: usize) -> Option<Entry> {
+        self.rewind_until(|entry| entry.write.is_some_and(|write| write.addr == addr))
+    }
+
+    /// Runs backwards until just before the instruction that produced the last output.
+    pub fn rewind_to_output(&mut self) -> Option<Entry> {
+        self.rewind_until(|entry| entry.output.is_some())
+    }
+
+    /// Runs backwards until a breakpoint is reached, returning its address, or to the start of
+    /// the history. A breakpoint on the current instruction is ignored.
+    pub fn reverse_cont(&mut self) -> Option<usize> {
+        while self.step_back().is_some() {
+            let inst_ptr = self.computer.inst_ptr();
+            if self.breakpoints.contains(&inst_ptr) {
+                return Some(inst_ptr);
+            }
+        }
+        None
+    }
+
+    fn rewind_until(&mut self, mut found: impl FnMut(&Entry) -> bool) -> Option<Entry> {
+        while let Some(entry) = self.step_back() {
+            if found(&entry) {
+                return Some(entry);
+            }
+        }
+        None
+    }
+
+    /// The first watched address the current instruction will access, if any.
+    fn watched_access(&self) -> Option<(usize, Access)> {
+        if self.watchpoints.is_empty() {
+            return None;
+        }
+
+        let op = self.computer.op_at(self.computer.inst_ptr()).ok()?;
+
+        let reads = op.sources().into_iter().map(|p| (p, Access::Read));
+        let writes = op.destination().into_iter().map(|p| (p, Access::Write));
+
+        reads.chain(writes).find_map(|(param, access)| {
+            let addr = self.computer.param_address(&param)?;
+            if addr < 0 {
+                return None;
+            }
+
+            let addr = addr as usize;
+            match self.watchpoints.get(&addr) {
+                Some(watch) if watch.covers(access) => Some((addr, access)),
+                _ => None,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+
+    fn debugger(source: &str) -> Debugger {
+        Debugger::new(Computer::new(assemble(source).unwrap()))
+    }
+
+    const COUNTDOWN: &str = "
+                in [n]
+        loop:   out [n]
+                add [n], -1, [n]
+                jt [n], loop
+                hlt
+        n:      data 0
+    ";
+
+    #[test]
+    fn stops_at_breakpoints() {
+        let mut debugger = debugger(COUNTDOWN);
+        debugger.computer_mut().push_input(2);
+        debugger.add_breakpoint(2);
+
+        assert_eq!(StopReason::Breakpoint(2), debugger.cont());
+        assert!(debugger.outputs().is_empty());
+
+        assert_eq!(StopReason::Breakpoint(2), debugger.cont());
+        assert_eq!(&[2], debugger.outputs());
+
+        assert!(debugger.remove_breakpoint(2));
+        assert_eq!(StopReason::Halted, debugger.cont());
+        assert_eq!(&[2, 1], debugger.outputs());
+    }
+
+    #[test]
+    fn stops_on_watched_access() {
+        let mut debugger = debugger(COUNTDOWN);
+        debugger.computer_mut().push_input(5);
+        debugger.watch(12, Access::Write);
+
+        assert_eq!(
+            StopReason::Watchpoint {
+                inst_ptr: 0,
+                addr: 12,
+                access: Access::Write
+            },
+            debugger.cont()
+        );
+        assert_eq!(5, debugger.computer().peek(12));
+
+        debugger.watch(12, Access::ReadWrite);
+        assert_eq!(
+            StopReason::Watchpoint {
+                inst_ptr: 2,
+                addr: 12,
+                access: Access::Read
+            },
+            debugger.cont()
+        );
+        assert_eq!(&[5], debugger.outputs());
+
+        assert!(debugger.unwatch(12));
+        assert_eq!(StopReason::Halted, debugger.cont());
+        assert_eq!(&[5, 4, 3, 2, 1], debugger.outputs());
+    }
+
+    #[test]
+    fn reports_input_and_faults() {
+        let mut debugger = debugger(COUNTDOWN);
+        assert_eq!(StopReason::NeedsInput, debugger.step());
+        assert_eq!(0, debugger.computer().inst_ptr());
+
+        debugger.computer_mut().poke(0, 42);
+        match debugger.step() {
+            StopReason::Fault(e) => assert_eq!(42, e.opcode()),
+            reason => panic!("unexpected {:?}", reason),
+        }
+    }
+
+    #[test]
+    fn runs_backwards() {
+        let mut debugger = debugger(COUNTDOWN);
+        debugger.computer_mut().push_input(5);
+        assert_eq!(StopReason::Halted, debugger.cont());
+        let recorded = debugger.history_len();
+        assert_eq!(StopReason::Halted, debugger.step());
+        assert_eq!(recorded, debugger.history_len());
+
+        let out = debugger.rewind_to_output().unwrap();
+        assert_eq!(Some(1), out.output);
+        assert_eq!(2, debugger.computer().inst_ptr());
+        assert_eq!(&[5, 4, 3, 2], debugger.outputs());
+
+        let write = debugger.run_back_to_write(12).unwrap();
+        assert_eq!(4, write.inst_ptr);
+        assert_eq!(4, debugger.computer().inst_ptr());
+        assert_eq!(2, debugger.computer().peek(12));
+
+        debugger.add_breakpoint(2);
+        assert_eq!(Some(2), debugger.reverse_cont());
+        assert_eq!(&[5, 4, 3], debugger.outputs());
+
+        assert_eq!(None, debugger.run_back_to_write(100));
+        assert_eq!(0, debugger.computer().inst_ptr());
+        assert_eq!(0, debugger.history_len());
+        assert!(debugger.outputs().is_empty());
+        assert!(debugger.step_back().is_none());
+
+        // The consumed input is back on the queue, so the run replays exactly.
+        debugger.remove_breakpoint(2);
+        assert_eq!(StopReason::Halted, debugger.cont());
+        assert_eq!(&[5, 4, 3, 2, 1], debugger.outputs());
+    }
+}
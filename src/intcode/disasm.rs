@@ -0,0 +1,397 @@
+//! Disassembler producing listings that `asm::assemble` turns back into the same program.
+//!
+//! Code is found by following control flow from the entry points. Jumps with immediate targets
+//! are followed; jumps through memory cannot be resolved statically, so code only reachable
+//! that way is listed as data.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::{Op, ParamMode, ParamWithMode};
+
+pub(crate) const DATA_PER_LINE: usize = 8;
+
+pub struct Disassembly {
+    program: Vec<i64>,
+    instructions: BTreeMap<usize, Op>,
+    labels: BTreeMap<usize, String>,
+}
+
+impl Disassembly {
+    pub fn new(program: &[i64]) -> Self {
+        Self::with_entry_points(program, &[0])
+    }
+
+    pub fn with_entry_points(program: &[i64], entry_points: &[usize]) -> Self {
+        let mut instructions = BTreeMap::new();
+        let mut claimed = vec![false; program.len()];
+        let mut pending = entry_points.to_vec();
+
+        while let Some(addr) = pending.pop() {
+            if addr >= program.len() || claimed[addr] {
+                continue;
+            }
+
+            let op = match Op::decode(addr, &program[addr..]) {
+                Ok(op) => op,
+                Err(_) => continue,
+            };
+
+            let end = addr + op.size();
+            if claimed[addr..end].iter().any(|c| *c) {
+                continue;
+            }
+
+            for c in &mut claimed[addr..end] {
+                *c = true;
+            }
+
+            instructions.insert(addr, op);
+            pending.extend(successors(addr, &op));
+        }
+
+        let mut disassembly = Self {
+            program: program.to_vec(),
+            instructions,
+            labels: BTreeMap::new(),
+        };
+        disassembly.assign_labels();
+        disassembly
+    }
+
+    pub fn program(&self) -> &[i64] {
+        &self.program
+    }
+
+    /// Reachable instructions keyed by their address.
+    pub fn instructions(&self) -> &BTreeMap<usize, Op> {
+        &self.instructions
+    }
+
+    pub fn labels(&self) -> &BTreeMap<usize, String> {
+        &self.labels
+    }
+
+    /// The address of the reachable instruction covering `addr`, if any.
+    pub fn instruction_containing(&self, addr: usize) -> Option<usize> {
+        self.instructions
+            .range(..=addr)
+            .next_back()
+            .filter(|(start, op)| addr < *start + op.size())
+            .map(|(start, _)| *start)
+    }
+
+    pub fn is_code(&self, addr: usize) -> bool {
+        self.instruction_containing(addr).is_some()
+    }
+
+    fn assign_labels(&mut self) {
+        let mut labels = BTreeMap::new();
+
+        for op in self.instructions.values() {
+            if let Some(target) = static_jump_target(op) {
+                if self.instructions.contains_key(&target) {
+                    labels.insert(target, format!("L{}", target));
+                }
+            }
+
+            for (mode, val) in op.params() {
+                if mode != ParamMode::Position || val < 0 || val as usize >= self.program.len() {
+                    continue;
+                }
+
+                let target = val as usize;
+                match self.instruction_containing(target) {
+                    Some(start) if start == target => {
+                        labels.insert(target, format!("L{}", target));
+                    }
+                    Some(start) => {
+                        labels.insert(start, format!("L{}", start));
+                    }
+                    None => {
+                        labels.entry(target).or_insert(format!("D{}", target));
+                    }
+                }
+            }
+        }
+
+        self.labels = labels;
+    }
+
+    fn fmt_param(&self, param: &ParamWithMode, is_jump_target: bool) -> String {
+        match param {
+            (ParamMode::Immediate, val) if is_jump_target && *val >= 0 => {
+                match self.labels.get(&(*val as usize)) {
+                    Some(label) => label.clone(),
+                    None => fmt_num(*val),
+                }
+            }
+            (ParamMode::Position, val) if *val >= 0 => {
+                let target = *val as usize;
+                if let Some(label) = self.labels.get(&target) {
+                    return format!("[{}]", label);
+                }
+
+                match self.instruction_containing(target) {
+                    Some(start) if self.labels.contains_key(&start) => {
+                        format!("[{} + {}]", self.labels[&start], target - start)
+                    }
+                    _ => format_param(param),
+                }
+            }
+            _ => format_param(param),
+        }
+    }
+
+    fn fmt_op(&self, op: &Op) -> String {
+        let jump_target = match op {
+            Op::JumpIfTrue(_, _) | Op::JumpIfFalse(_, _) => Some(1),
+            _ => None,
+        };
+
+        let params: Vec<_> = op
+            .params()
+            .iter()
+            .enumerate()
+            .map(|(i, param)| self.fmt_param(param, jump_target == Some(i)))
+            .collect();
+
+        if params.is_empty() {
+            op.mnemonic().to_string()
+        } else {
+            format!("{} {}", op.mnemonic(), params.join(", "))
+        }
+    }
+
+    fn write_line(
+        &self,
+        f: &mut fmt::Formatter,
+        addr: usize,
+        text: &str,
+        comment: &str,
+    ) -> fmt::Result {
+        let mut label = self
+            .labels
+            .get(&addr)
+            .map(|label| format!("{}:", label))
+            .unwrap_or_default();
+
+        if label.len() >= 8 {
+            writeln!(f, "{}", label)?;
+            label.clear();
+        }
+
+        writeln!(f, "{:<8}{:<32}; {:>5}: {}", label, text, addr, comment)
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut addr = 0;
+
+        while addr < self.program.len() {
+            if let Some(op) = self.instructions.get(&addr) {
+                let words = &self.program[addr..addr + op.size()];
+                let raw = words
+                    .iter()
+                    .map(|w| w.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+
+                // The assembler rejects writes to immediates, so those stay data too.
+                let immediate_write = matches!(op.destination(), Some((ParamMode::Immediate, _)));
+                if immediate_write {
+                    let comment = format!("{} (writes to an immediate: {})", raw, op);
+                    self.write_line(f, addr, &fmt_data(words), &comment)?;
+                } else if op.encode() == words {
+                    self.write_line(f, addr, &self.fmt_op(op), &raw)?;
+                } else {
+                    let comment = format!("{} (non-canonical encoding of {})", raw, op);
+                    self.write_line(f, addr, &fmt_data(words), &comment)?;
+                }
+
+                addr += op.size();
+                continue;
+            }
+
+            let start = addr;
+            addr += 1;
+            while addr < self.program.len()
+                && addr - start < DATA_PER_LINE
+                && !self.instructions.contains_key(&addr)
+                && !self.labels.contains_key(&addr)
+            {
+                addr += 1;
+            }
+
+            self.write_line(f, start, &fmt_data(&self.program[start..addr]), "data")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic())?;
+
+        for (i, param) in self.params().iter().enumerate() {
+            let sep = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", sep, format_param(param))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats a parameter in assembler syntax.
+pub fn format_param(param: &ParamWithMode) -> String {
+    match *param {
+        (ParamMode::Position, val) => format!("[{}]", fmt_num(val)),
+        (ParamMode::Immediate, val) => fmt_num(val),
+        (ParamMode::Relative, 0) => "[rb]".to_string(),
+        (ParamMode::Relative, val) if val < 0 && val != i64::MIN => format!("[rb - {}]", -val),
+        (ParamMode::Relative, val) => format!("[rb + {}]", fmt_num(val)),
+    }
+}
+
+pub fn disassemble(program: &[i64]) -> String {
+    Disassembly::new(program).to_string()
+}
+
+/// Addresses control may continue at after executing `op`, ignoring jumps through memory.
+pub(crate) fn successors(addr: usize, op: &Op) -> Vec<usize> {
+    let next = addr + op.size();
+
+    match op {
+        Op::Halt => vec![],
+        Op::JumpIfTrue(cond, _) | Op::JumpIfFalse(cond, _) => {
+            let jumps_when_nonzero = matches!(op, Op::JumpIfTrue(_, _));
+            let target = static_jump_target(op);
+
+            match cond {
+                (ParamMode::Immediate, val) if (*val != 0) == jumps_when_nonzero => {
+                    target.into_iter().collect()
+                }
+                (ParamMode::Immediate, _) => vec![next],
+                _ => target.into_iter().chain(Some(next)).collect(),
+            }
+        }
+        _ => vec![next],
+    }
+}
+
+pub(crate) fn static_jump_target(op: &Op) -> Option<usize> {
+    match op {
+        Op::JumpIfTrue(_, (ParamMode::Immediate, target))
+        | Op::JumpIfFalse(_, (ParamMode::Immediate, target))
+            if *target >= 0 =>
+        {
+            Some(*target as usize)
+        }
+        _ => None,
+    }
+}
+
+fn fmt_num(val: i64) -> String {
+    if val == i64::MIN {
+        // The assembler negates a positive literal, which cannot hold i64::MIN.
+        format!("({} - 1)", val + 1)
+    } else {
+        val.to_string()
+    }
+}
+
+fn fmt_data(words: &[i64]) -> String {
+    let values: Vec<_> = words.iter().map(|w| fmt_num(*w)).collect();
+    format!("data {}", values.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+    use crate::intcode::read_input;
+    use crate::util;
+
+    #[test]
+    fn separates_code_from_data() {
+        // jt 1, 7 skips over three words of data.
+        let program = vec![1105, 1, 7, 3, 4, 5, 6, 4, 3, 99];
+        let disassembly = Disassembly::new(&program);
+
+        let code: Vec<_> = (0..program.len())
+            .filter(|addr| disassembly.is_code(*addr))
+            .collect();
+        assert_eq!(vec![0, 1, 2, 7, 8, 9], code);
+
+        let listing = disassembly.to_string();
+        let lines: Vec<_> = listing.lines().map(|line| line.trim_end()).collect();
+        assert_eq!(
+            vec![
+                "        jt 1, L7                        ;     0: 1105 1 7",
+                "D3:     data 3, 4, 5, 6                 ;     3: data",
+                "L7:     out [D3]                        ;     7: 4 3",
+                "        hlt                             ;     9: 99",
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn operands_into_instructions_use_offsets() {
+        // Patches the immediate of its own output instruction before running it.
+        let program = assemble("add 0, 42, [5]\nout 0\nhlt").unwrap();
+        let listing = disassemble(&program);
+        assert!(listing.contains("add 0, 42, [L4 + 1]"), "{}", listing);
+        assert!(listing.contains("L4:     out 0"), "{}", listing);
+    }
+
+    #[test]
+    fn non_canonical_encodings_are_kept_as_data() {
+        let program = vec![10104, 7, 99];
+        let listing = disassemble(&program);
+        assert!(listing.contains("data 10104, 7"), "{}", listing);
+        assert!(
+            listing.contains("non-canonical encoding of out 7"),
+            "{}",
+            listing
+        );
+        assert_eq!(program, assemble(&listing).unwrap());
+    }
+
+    #[test]
+    fn immediate_writes_are_kept_as_data() {
+        let program = vec![11101, 1, 1, 0, 99];
+        let listing = disassemble(&program);
+        assert!(listing.contains("data 11101, 1, 1, 0"), "{}", listing);
+        assert!(
+            listing.contains("writes to an immediate: add 1, 1, 0"),
+            "{}",
+            listing
+        );
+        assert_eq!(program, assemble(&listing).unwrap());
+    }
+
+    #[test]
+    fn op_display() {
+        let op = Op::decode(0, &[21001, 4, -3, -2]).unwrap();
+        assert_eq!("add [4], -3, [rb - 2]", op.to_string());
+        assert_eq!("hlt", Op::Halt.to_string());
+    }
+
+    #[test]
+    fn round_trips_puzzle_inputs() {
+        for name in &["day2.txt", "day5.txt", "day7.txt", "day9.txt", "day11.txt"] {
+            let program = read_input(&util::read_input_file(name)[..]);
+            let listing = disassemble(&program);
+            assert_eq!(program, assemble(&listing).unwrap(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn round_trips_extreme_values() {
+        let program = vec![104, i64::MIN, 204, i64::MIN, 99, i64::MIN];
+        assert_eq!(program, assemble(&disassemble(&program)).unwrap());
+    }
+}
@@ -0,0 +1,563 @@
+//! Differential fuzzing of the Intcode backends.
+//!
+//! `generate` builds random but well-formed programs: mostly valid instructions whose operands
+//! point at the program's own code and data, with the occasional immediate destination, stray
+//! word or huge constant to exercise the fault paths. Each `Backend` runs a case under the same
+//! memory limit and step budget, and any difference in outputs, final memory or how the run
+//! ended is a `Failure`, which `minimize` shrinks to a small reproducer.
+//!
+//! Everything is deterministic given a seed, so a failure can be replayed with `fuzz`.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use num::BigInt;
+
+use super::budget::{Budget, Outcome};
+use super::compile::{Compiled, CompiledComputer};
+use super::memory::Memory;
+use super::snapshot::Snapshot;
+use super::word::{self, Checked, Word};
+use super::{Computer, IntcodeError, ReturnMode};
+
+/// Memory limit for every run, so a stray write can't allocate gigabytes.
+pub const MEMORY_LIMIT: usize = 4096;
+
+/// Instructions each run may execute, so infinite loops end.
+pub const MAX_STEPS: u64 = 2000;
+
+/// A small xorshift generator, so runs are reproducible without extra dependencies.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Zero is a fixed point of xorshift.
+        Rng(seed ^ 0x9e37_79b9_7f4a_7c15)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A value in `0..n`.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n.max(1)
+    }
+
+    /// A value in `lo..=hi`.
+    pub fn range(&mut self, lo: i64, hi: i64) -> i64 {
+        lo + self.below((hi - lo) as u64 + 1) as i64
+    }
+
+    /// True with probability `percent`/100.
+    pub fn chance(&mut self, percent: u64) -> bool {
+        self.below(100) < percent
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Case {
+    pub program: Vec<i64>,
+    pub inputs: Vec<i64>,
+}
+
+impl fmt::Display for Case {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |words: &[i64]| {
+            let words: Vec<_> = words.iter().map(|w| w.to_string()).collect();
+            words.join(",")
+        };
+        write!(
+            f,
+            "program: {}\ninputs: {}",
+            join(&self.program),
+            join(&self.inputs)
+        )
+    }
+}
+
+const DATA_WORDS: usize = 8;
+
+/// A random program of up to `max_instructions` instructions followed by a little data, plus
+/// a few inputs.
+pub fn generate(rng: &mut Rng, max_instructions: usize) -> Case {
+    let count = 1 + rng.below(max_instructions as u64) as usize;
+
+    // Pick the opcodes first so operands can refer to instruction starts.
+    let mut opcodes = vec![];
+    let mut starts = vec![];
+    let mut len = 0;
+    for _ in 0..count {
+        let opcode = match rng.below(20) {
+            0..=2 => 1,
+            3..=4 => 2,
+            5..=6 => 3,
+            7..=9 => 4,
+            10..=11 => 5,
+            12..=13 => 6,
+            14 => 7,
+            15 => 8,
+            16..=17 => 9,
+            18 => 99,
+            // Not an instruction at all.
+            _ => 0,
+        };
+        starts.push(len);
+        len += match opcode {
+            1 | 2 | 7 | 8 => 4,
+            5 | 6 => 3,
+            3 | 4 | 9 => 2,
+            _ => 1,
+        };
+        opcodes.push(opcode);
+    }
+    let code_len = len + 1;
+    let total = code_len + DATA_WORDS;
+
+    let mut program = vec![];
+    for opcode in opcodes {
+        if opcode == 0 {
+            program.push(rng.range(-100, 100) * 100 + 10);
+            continue;
+        }
+
+        let arity = match opcode {
+            1 | 2 | 7 | 8 => 3,
+            5 | 6 => 2,
+            99 => 0,
+            _ => 1,
+        };
+        let writes = matches!(opcode, 1 | 2 | 3 | 7 | 8);
+
+        let mut raw_op = opcode;
+        let mut place = 100;
+        let mut params = vec![];
+        for i in 0..arity {
+            let is_dest = writes && i == arity - 1;
+            let is_target = matches!(opcode, 5 | 6) && i == 1;
+
+            let mode = match rng.below(100) {
+                _ if is_dest && rng.chance(3) => 1,
+                0..=44 if !is_dest => 1,
+                0..=79 => 0,
+                _ => 2,
+            };
+            raw_op += place * mode;
+            place *= 10;
+
+            let val = match mode {
+                1 if is_target && rng.chance(90) => {
+                    starts[rng.below(starts.len() as u64) as usize] as i64
+                }
+                1 if rng.chance(3) => [i64::MAX, i64::MIN, 1 << 40][rng.below(3) as usize],
+                1 => rng.range(-10, 10),
+                // Mostly data, sometimes code.
+                0 if rng.chance(80) => rng.range(code_len as i64, total as i64 - 1),
+                0 => rng.range(0, total as i64 - 1),
+                _ => rng.range(-4, total as i64),
+            };
+            params.push(val);
+        }
+
+        program.push(raw_op);
+        program.extend(params);
+    }
+
+    program.push(99);
+    program.extend((0..DATA_WORDS).map(|_| rng.range(-5, 20)));
+
+    let inputs = (0..rng.below(5)).map(|_| rng.range(-5, 20)).collect();
+    Case { program, inputs }
+}
+
+/// How a run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum End {
+    Halted,
+    NeedsInput,
+    OutOfSteps,
+    Fault(IntcodeError),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    pub outputs: Vec<i64>,
+    pub memory: Vec<i64>,
+    pub end: End,
+}
+
+/// Something that can run Intcode programs.
+pub trait Backend {
+    fn name(&self) -> &str;
+
+    /// Runs `case` with every input queued up front, for at most `MAX_STEPS` instructions.
+    /// `None` means the backend can't judge this case, e.g. its words overflowed where `i64`
+    /// would wrap.
+    fn run(&self, case: &Case) -> Option<Run>;
+}
+
+fn limited<W: Word>(program: &[i64], dense: bool) -> Computer<W> {
+    let words = word::widen(program);
+    let memory = if dense {
+        Memory::dense(words)
+    } else {
+        Memory::new(words)
+    };
+    Computer::with_memory(memory.with_limit(MEMORY_LIMIT))
+}
+
+/// Runs a machine under a step budget, narrowing its words back to `i64`.
+fn drive<W: Word>(computer: &mut Computer<W>, steps: u64, outputs: &mut Vec<i64>) -> Option<End> {
+    let mut budget = Budget::new().steps(steps);
+    loop {
+        let end = match computer.run_budgeted(&mut budget) {
+            Ok(Outcome::Returned(ReturnMode::Output(val))) => {
+                outputs.push(val.to_i64()?);
+                continue;
+            }
+            Ok(Outcome::Returned(ReturnMode::Halt)) => End::Halted,
+            Ok(Outcome::Returned(ReturnMode::NeedsInput)) => End::NeedsInput,
+            Ok(Outcome::BudgetExhausted(_)) => End::OutOfSteps,
+            Err(IntcodeError::Overflow { .. }) => return None,
+            Err(e) => End::Fault(e),
+        };
+        return Some(end);
+    }
+}
+
+fn finish<W: Word>(computer: &Computer<W>, outputs: Vec<i64>, end: End) -> Option<Run> {
+    let memory = computer
+        .memory()
+        .to_vec()?
+        .iter()
+        .map(Word::to_i64)
+        .collect::<Option<_>>()?;
+    Some(Run {
+        outputs,
+        memory,
+        end,
+    })
+}
+
+/// The interpreter with words of type `W`, on paged or dense memory.
+pub struct Interpreter<W> {
+    name: &'static str,
+    dense: bool,
+    words: PhantomData<W>,
+}
+
+impl<W> Interpreter<W> {
+    pub fn new(name: &'static str, dense: bool) -> Self {
+        Self {
+            name,
+            dense,
+            words: PhantomData,
+        }
+    }
+}
+
+impl<W: Word> Backend for Interpreter<W> {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn run(&self, case: &Case) -> Option<Run> {
+        let mut computer = limited::<W>(&case.program, self.dense);
+        for val in &case.inputs {
+            computer.push_input(W::from_i64(*val));
+        }
+
+        let mut outputs = vec![];
+        let end = drive(&mut computer, MAX_STEPS, &mut outputs)?;
+        finish(&computer, outputs, end)
+    }
+}
+
+/// The interpreter with `BigInt` words. These only match `i64` words while nothing overflows,
+/// so cases where a `Checked` run overflows are skipped.
+pub struct Wide;
+
+impl Backend for Wide {
+    fn name(&self) -> &str {
+        "bigint"
+    }
+
+    fn run(&self, case: &Case) -> Option<Run> {
+        Interpreter::<Checked>::new("checked", false).run(case)?;
+        Interpreter::<BigInt>::new("bigint", false).run(case)
+    }
+}
+
+/// The closure-threaded compiler, falling back to the interpreter where it must.
+pub struct CompiledBackend;
+
+impl Backend for CompiledBackend {
+    fn name(&self) -> &str {
+        "compiled"
+    }
+
+    fn run(&self, case: &Case) -> Option<Run> {
+        let code = Compiled::new(&case.program);
+        let mut computer = CompiledComputer::new(&code, limited(&case.program, false));
+        for val in &case.inputs {
+            computer.push_input(*val);
+        }
+
+        let mut outputs = vec![];
+        let mut end = End::OutOfSteps;
+        for _ in 0..MAX_STEPS {
+            match computer.step() {
+                Ok(None) => continue,
+                Ok(Some(ReturnMode::Output(val))) => {
+                    outputs.push(val);
+                    continue;
+                }
+                Ok(Some(ReturnMode::Halt)) => end = End::Halted,
+                Ok(Some(ReturnMode::NeedsInput)) => end = End::NeedsInput,
+                Err(e) => end = End::Fault(e),
+            }
+            break;
+        }
+
+        finish(computer.computer(), outputs, end)
+    }
+}
+
+/// The interpreter, reporting every instruction to a tracer.
+pub struct Traced;
+
+impl Backend for Traced {
+    fn name(&self) -> &str {
+        "traced"
+    }
+
+    fn run(&self, case: &Case) -> Option<Run> {
+        let mut computer: Computer = limited(&case.program, false);
+        for val in &case.inputs {
+            computer.push_input(*val);
+        }
+
+        let mut events = vec![];
+        let mut outputs = vec![];
+        let mut end = End::OutOfSteps;
+        for _ in 0..MAX_STEPS {
+            match computer.step_traced(&mut events) {
+                Ok(None) => continue,
+                Ok(Some(ReturnMode::Output(val))) => {
+                    outputs.push(val);
+                    continue;
+                }
+                Ok(Some(ReturnMode::Halt)) => end = End::Halted,
+                Ok(Some(ReturnMode::NeedsInput)) => end = End::NeedsInput,
+                Err(e) => end = End::Fault(e),
+            }
+            break;
+        }
+
+        finish(&computer, outputs, end)
+    }
+}
+
+/// The interpreter, stopped halfway through its budget, saved to bytes and resumed from them.
+pub struct Resumed;
+
+impl Backend for Resumed {
+    fn name(&self) -> &str {
+        "resumed"
+    }
+
+    fn run(&self, case: &Case) -> Option<Run> {
+        let mut computer: Computer = limited(&case.program, false);
+        for val in &case.inputs {
+            computer.push_input(*val);
+        }
+
+        let mut outputs = vec![];
+        let end = drive(&mut computer, MAX_STEPS / 2, &mut outputs)?;
+        if end != End::OutOfSteps {
+            return finish(&computer, outputs, end);
+        }
+
+        let snapshot = Snapshot::from_bytes(&computer.snapshot().to_bytes()).ok()?;
+        let mut resumed: Computer = limited(&[], false);
+        resumed.restore(&snapshot).ok()?;
+        let end = drive(&mut resumed, MAX_STEPS - MAX_STEPS / 2, &mut outputs)?;
+        finish(&resumed, outputs, end)
+    }
+}
+
+/// Every backend in the crate.
+pub fn backends() -> Vec<Box<dyn Backend>> {
+    vec![
+        Box::new(Interpreter::<i64>::new("paged", false)),
+        Box::new(Interpreter::<i64>::new("dense", true)),
+        Box::new(Interpreter::<Checked>::new("checked", false)),
+        Box::new(Wide),
+        Box::new(CompiledBackend),
+        Box::new(Traced),
+        Box::new(Resumed),
+    ]
+}
+
+/// A case the backends disagree on, with each backend's run.
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub case: Case,
+    pub runs: Vec<(String, Run)>,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "backends disagree on\n{}", self.case)?;
+        for (name, run) in &self.runs {
+            writeln!(f, "{:>10}: {:?}, outputs {:?}", name, run.end, run.outputs)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `case` on every backend, returning the runs if any two that could judge it disagree.
+pub fn check(case: &Case, backends: &[Box<dyn Backend>]) -> Option<Failure> {
+    let runs: Vec<_> = backends
+        .iter()
+        .filter_map(|backend| Some((backend.name().to_string(), backend.run(case)?)))
+        .collect();
+
+    if runs.windows(2).all(|pair| pair[0].1 == pair[1].1) {
+        return None;
+    }
+
+    Some(Failure {
+        case: case.clone(),
+        runs,
+    })
+}
+
+/// Shrinks a failing case while the backends keep disagreeing on it: dropping inputs, then
+/// removing runs of program words, then simplifying the words that are left.
+pub fn minimize(failure: Failure, backends: &[Box<dyn Backend>]) -> Failure {
+    let mut best = failure;
+
+    loop {
+        let mut candidates = vec![];
+        let case = &best.case;
+
+        for i in 0..case.inputs.len() {
+            let mut smaller = case.clone();
+            smaller.inputs.remove(i);
+            candidates.push(smaller);
+        }
+        // Up to a whole instruction at a time.
+        for len in (1..=4).rev() {
+            for i in 0..case.program.len().saturating_sub(len - 1) {
+                let mut smaller = case.clone();
+                smaller.program.drain(i..i + len);
+                candidates.push(smaller);
+            }
+        }
+        for i in 0..case.program.len() {
+            let word = case.program[i];
+            for simpler in &[0, 1, word / 2] {
+                if simpler.unsigned_abs() < word.unsigned_abs() {
+                    let mut smaller = case.clone();
+                    smaller.program[i] = *simpler;
+                    candidates.push(smaller);
+                }
+            }
+        }
+
+        match candidates.iter().find_map(|case| check(case, backends)) {
+            Some(smaller) => best = smaller,
+            None => return best,
+        }
+    }
+}
+
+/// Checks `iterations` random cases starting from `seed`, returning the first failure found,
+/// minimized.
+pub fn fuzz(
+    seed: u64,
+    iterations: usize,
+    backends: &[Box<dyn Backend>],
+) -> std::result::Result<(), Failure> {
+    let mut rng = Rng::new(seed);
+    for _ in 0..iterations {
+        let case = generate(&mut rng, 24);
+        if let Some(failure) = check(&case, backends) {
+            return Err(minimize(failure, backends));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn env_or(name: &str, default: u64) -> u64 {
+        env::var(name)
+            .ok()
+            .and_then(|val| val.parse().ok())
+            .unwrap_or(default)
+    }
+
+    /// Set `INTCODE_FUZZ_ITERATIONS` and `INTCODE_FUZZ_SEED` for a longer or different run.
+    #[test]
+    fn backends_agree() {
+        let seed = env_or("INTCODE_FUZZ_SEED", 2019);
+        let iterations = env_or("INTCODE_FUZZ_ITERATIONS", 300) as usize;
+        if let Err(failure) = fuzz(seed, iterations, &backends()) {
+            panic!("seed {}: {}", seed, failure);
+        }
+    }
+
+    #[test]
+    fn generated_programs_do_things() {
+        let mut rng = Rng::new(1);
+        let runs: Vec<_> = (0..200)
+            .filter_map(|_| Interpreter::<i64>::new("paged", false).run(&generate(&mut rng, 24)))
+            .collect();
+
+        let count = |f: fn(&Run) -> bool| runs.iter().filter(|run| f(run)).count();
+        assert!(count(|run| run.end == End::Halted) > 10);
+        assert!(count(|run| matches!(run.end, End::Fault(_))) > 10);
+        assert!(count(|run| !run.outputs.is_empty()) > 10);
+    }
+
+    /// Pretends 7 can't be output.
+    struct NoSevens;
+
+    impl Backend for NoSevens {
+        fn name(&self) -> &str {
+            "no-sevens"
+        }
+
+        fn run(&self, case: &Case) -> Option<Run> {
+            let mut run = Interpreter::<i64>::new("paged", false).run(case)?;
+            for val in &mut run.outputs {
+                if *val == 7 {
+                    *val = 8;
+                }
+            }
+            Some(run)
+        }
+    }
+
+    #[test]
+    fn minimizes_failures() {
+        let backends: Vec<Box<dyn Backend>> = vec![
+            Box::new(Interpreter::<i64>::new("paged", false)),
+            Box::new(NoSevens),
+        ];
+        let failure = fuzz(7, 1000, &backends).unwrap_err();
+
+        assert!(failure.case.program.len() <= 3, "{}", failure);
+        assert!(failure.case.inputs.len() <= 1, "{}", failure);
+        assert!(failure.runs[0].1.outputs.contains(&7), "{}", failure);
+    }
+}
@@ -0,0 +1,135 @@
+//! Input and output endpoints for `Computer::run_io`.
+//!
+//! Channels let each machine run on its own thread and block until its input arrives, so
+//! pipelines and rings of machines are just a matter of handing the right `Sender`s and
+//! `Receiver`s to each thread.
+
+use std::collections::VecDeque;
+use std::sync::mpsc::{Receiver, Sender, SyncSender};
+
+pub trait Input {
+    /// The next input value, blocking if necessary. `None` means no more input will arrive.
+    fn read(&mut self) -> Option<i64>;
+}
+
+pub trait Output {
+    fn write(&mut self, val: i64);
+}
+
+impl<T: Input + ?Sized> Input for &mut T {
+    fn read(&mut self) -> Option<i64> {
+        (**self).read()
+    }
+}
+
+impl<T: Output + ?Sized> Output for &mut T {
+    fn write(&mut self, val: i64) {
+        (**self).write(val)
+    }
+}
+
+/// Blocks until a value is sent; input ends once every sender has been dropped.
+impl Input for Receiver<i64> {
+    fn read(&mut self) -> Option<i64> {
+        self.recv().ok()
+    }
+}
+
+impl Input for VecDeque<i64> {
+    fn read(&mut self) -> Option<i64> {
+        self.pop_front()
+    }
+}
+
+/// Values sent after the receiver has been dropped are discarded, like writes to a closed pipe.
+impl Output for Sender<i64> {
+    fn write(&mut self, val: i64) {
+        let _ = self.send(val);
+    }
+}
+
+/// Blocks while the channel is full. Values sent after the receiver has been dropped are
+/// discarded.
+impl Output for SyncSender<i64> {
+    fn write(&mut self, val: i64) {
+        let _ = self.send(val);
+    }
+}
+
+impl Output for Vec<i64> {
+    fn write(&mut self, val: i64) {
+        self.push(val);
+    }
+}
+
+impl Output for VecDeque<i64> {
+    fn write(&mut self, val: i64) {
+        self.push_back(val);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{read_input, Computer, IntcodeError};
+    use std::sync::mpsc;
+    use std::thread;
+
+    // Doubles every input until it reads a zero.
+    const DOUBLER: &[u8] = b"3,20,1006,20,14,1002,20,2,20,4,20,1105,1,0,99";
+
+    #[test]
+    fn pipeline_across_threads() {
+        let (in_tx, in_rx) = mpsc::channel();
+        let (mid_tx, mid_rx) = mpsc::channel();
+        let (out_tx, out_rx) = mpsc::channel();
+
+        let stages = vec![(in_rx, mid_tx), (mid_rx, out_tx)];
+        let handles: Vec<_> = stages
+            .into_iter()
+            .map(|(mut rx, mut tx)| {
+                let mut computer = Computer::new(read_input(DOUBLER));
+                thread::spawn(move || computer.run_io(&mut rx, &mut tx))
+            })
+            .collect();
+
+        for val in &[1, 2, 3] {
+            in_tx.send(*val).unwrap();
+        }
+        assert_eq!(vec![4, 8, 12], out_rx.iter().take(3).collect::<Vec<_>>());
+
+        // The first stage halts on the zero without forwarding it, which closes the second
+        // stage's input.
+        in_tx.send(0).unwrap();
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(Ok(()), results[0]);
+        assert!(matches!(
+            results[1],
+            Err(IntcodeError::InputExhausted { inst_ptr: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn closed_input_is_exhausted() {
+        let (tx, mut rx) = mpsc::channel();
+        tx.send(5).unwrap();
+        drop(tx);
+
+        let mut outputs = vec![];
+        let mut computer = Computer::new(read_input(DOUBLER));
+        assert!(matches!(
+            computer.run_io(&mut rx, &mut outputs),
+            Err(IntcodeError::InputExhausted { inst_ptr: 0, .. })
+        ));
+        assert_eq!(vec![10], outputs);
+    }
+
+    #[test]
+    fn queues() {
+        let mut input: VecDeque<_> = vec![1, 7, 0].into();
+        let mut output = VecDeque::new();
+        let mut computer = Computer::new(read_input(DOUBLER));
+        computer.run_io(&mut input, &mut output).unwrap();
+        assert_eq!(vec![2, 14], Vec::from(output));
+    }
+}
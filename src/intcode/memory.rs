@@ -0,0 +1,401 @@
+//! Intcode memory backends.
+//!
+//! `Memory::new` stores words in pages that are only allocated once touched, so a program writing
+//! to a huge address costs one page rather than everything below it. Pages are reference counted
+//! and copied on first write, so cloning a paged `Memory` only copies the page table and forked
+//! machines share everything they don't modify. `Memory::dense` keeps a flat `Vec` instead, which
+//! is faster for small programs but grows up to any address written and copies on clone, so it
+//! never grows past `VEC_LIMIT` words.
+//!
+//! Either kind can be given a limit, after which accesses by a running program fail with
+//! `IntcodeError::AddressOutOfRange` instead of allocating. Dense memory is limited to
+//! `VEC_LIMIT` unless given a lower limit.
+
+use std::array;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::Arc;
+
+use super::word::Word;
+
+pub const PAGE_SIZE: usize = 128;
+
+/// The highest address that can be written. One more would leave no room for the length.
+pub const MAX_ADDRESS: usize = usize::MAX - 1;
+
+/// The most words `to_vec` will copy out, and the most dense memory will grow to.
+pub const VEC_LIMIT: usize = 1 << 24;
+
+type Page<W> = Arc<[W; PAGE_SIZE]>;
+
+#[derive(Clone)]
+enum Storage<W> {
+    Dense(Vec<W>),
+    Paged(BTreeMap<usize, Page<W>>),
+}
+
+#[derive(Clone)]
+pub struct Memory<W = i64> {
+    storage: Storage<W>,
+    len: usize,
+    limit: Option<usize>,
+}
+
+impl<W: Word> Memory<W> {
+    /// Sparse, copy-on-write paged memory.
+    pub fn new(words: Vec<W>) -> Self {
+        let mut pages = BTreeMap::new();
+        for (idx, chunk) in words.chunks(PAGE_SIZE).enumerate() {
+            let mut page: [W; PAGE_SIZE] = array::from_fn(|_| W::zero());
+            page[..chunk.len()].clone_from_slice(chunk);
+            pages.insert(idx, Arc::new(page));
+        }
+
+        Self {
+            storage: Storage::Paged(pages),
+            len: words.len(),
+            limit: None,
+        }
+    }
+
+    /// Contiguous memory backed by a single `Vec`, limited to `VEC_LIMIT` words.
+    pub fn dense(words: Vec<W>) -> Self {
+        Self {
+            len: words.len(),
+            storage: Storage::Dense(words),
+            limit: Some(VEC_LIMIT),
+        }
+    }
+
+    /// Restricts running programs to addresses below `limit`. Dense memory can't be given a
+    /// limit past `VEC_LIMIT`.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(match self.storage {
+            Storage::Dense(_) => limit.min(VEC_LIMIT),
+            Storage::Paged(_) => limit,
+        });
+        self
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    pub fn is_dense(&self) -> bool {
+        matches!(self.storage, Storage::Dense(_))
+    }
+
+    /// Whether a running program may access `addr`.
+    pub fn in_bounds(&self, addr: usize) -> bool {
+        self.limit.is_none_or(|limit| addr < limit)
+    }
+
+    /// Whether `set` can write `addr` at all, whatever the limit: anything up to `MAX_ADDRESS`
+    /// for paged memory, and below `VEC_LIMIT` for dense memory.
+    pub fn can_hold(&self, addr: usize) -> bool {
+        match self.storage {
+            Storage::Dense(_) => addr < VEC_LIMIT,
+            Storage::Paged(_) => addr <= MAX_ADDRESS,
+        }
+    }
+
+    /// Replaces the contents with `words`, keeping the kind of storage and the limit.
+    pub fn load(&mut self, words: Vec<W>) {
+        let fresh = match self.storage {
+            Storage::Dense(_) => Self::dense(words),
+            Storage::Paged(_) => Self::new(words),
+        };
+
+        *self = Self {
+            limit: self.limit,
+            ..fresh
+        };
+    }
+
+    /// One past the highest address that has been loaded or written.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Reads a cell; cells that were never written read as zero.
+    pub fn get(&self, addr: usize) -> W {
+        match &self.storage {
+            Storage::Dense(words) => words.get(addr).cloned().unwrap_or_else(W::zero),
+            Storage::Paged(pages) => match pages.get(&(addr / PAGE_SIZE)) {
+                Some(page) => page[addr % PAGE_SIZE].clone(),
+                None => W::zero(),
+            },
+        }
+    }
+
+    /// Writes a cell, copying its page first if another `Memory` shares it. The limit is not
+    /// checked here; it only applies to the program's own accesses.
+    ///
+    /// Panics unless the memory `can_hold` the address.
+    pub fn set(&mut self, addr: usize, val: W) {
+        assert!(self.can_hold(addr), "address {} is out of range", addr);
+        let end = addr + 1;
+
+        match &mut self.storage {
+            Storage::Dense(words) => {
+                if addr >= words.len() {
+                    words.resize(end, W::zero());
+                }
+                words[addr] = val;
+            }
+            Storage::Paged(pages) => {
+                let page = pages
+                    .entry(addr / PAGE_SIZE)
+                    .or_insert_with(|| Arc::new(array::from_fn(|_| W::zero())));
+                Arc::make_mut(page)[addr % PAGE_SIZE] = val;
+            }
+        }
+
+        if addr >= self.len {
+            self.len = end;
+        }
+    }
+
+    /// Whether `len` words fit under the limit and in this kind of storage.
+    pub fn fits(&self, len: usize) -> bool {
+        len == 0 || (self.in_bounds(len - 1) && self.can_hold(len - 1))
+    }
+
+    /// Replaces the contents with `len` words, all zero except for the given pages of words,
+    /// keeping the kind of storage and the limit. Dense memory allocates all `len` words.
+    ///
+    /// Returns `false`, leaving the contents alone, if `len` doesn't fit or a page reaches past
+    /// it.
+    #[must_use]
+    pub fn load_pages(&mut self, len: usize, pages: Vec<(usize, Vec<W>)>) -> bool {
+        let in_range = pages.iter().all(|(idx, words)| {
+            idx.checked_mul(PAGE_SIZE)
+                .and_then(|start| start.checked_add(words.len()))
+                .is_some_and(|end| end <= len)
+        });
+        if !in_range || !self.fits(len) {
+            return false;
+        }
+
+        self.load(vec![]);
+        for (idx, words) in pages {
+            for (offset, val) in words.into_iter().enumerate() {
+                self.set(idx * PAGE_SIZE + offset, val);
+            }
+        }
+
+        if let Storage::Dense(words) = &mut self.storage {
+            words.resize(len, W::zero());
+        }
+        self.len = len;
+        true
+    }
+
+    /// Number of allocated pages. Dense memory has none.
+    pub fn pages(&self) -> usize {
+        match &self.storage {
+            Storage::Dense(_) => 0,
+            Storage::Paged(pages) => pages.len(),
+        }
+    }
+
+    /// Number of allocated pages that are still shared with another `Memory`.
+    pub fn shared_pages(&self) -> usize {
+        match &self.storage {
+            Storage::Dense(_) => 0,
+            Storage::Paged(pages) => pages
+                .values()
+                .filter(|page| Arc::strong_count(page) > 1)
+                .count(),
+        }
+    }
+
+    /// The allocated pages as (page index, words), in address order and cut off at `len`.
+    /// Pages never written are missing and read as zero; dense memory reports every page.
+    pub fn chunks(&self) -> Vec<(usize, &[W])> {
+        match &self.storage {
+            Storage::Dense(words) => words.chunks(PAGE_SIZE).enumerate().collect(),
+            Storage::Paged(pages) => pages
+                .iter()
+                .filter(|(idx, _)| *idx * PAGE_SIZE < self.len)
+                .map(|(idx, page)| {
+                    let end = (self.len - idx * PAGE_SIZE).min(PAGE_SIZE);
+                    (*idx, &page[..end])
+                })
+                .collect(),
+        }
+    }
+
+    /// Every word up to `len`, or `None` if that is more than `VEC_LIMIT` words, as it can be
+    /// for a sparse memory that was written at a high address.
+    pub fn to_vec(&self) -> Option<Vec<W>> {
+        if self.len > VEC_LIMIT {
+            return None;
+        }
+
+        match &self.storage {
+            Storage::Dense(words) => Some(words.clone()),
+            Storage::Paged(_) => Some((0..self.len).map(|addr| self.get(addr)).collect()),
+        }
+    }
+}
+
+impl<W: Word> Default for Memory<W> {
+    fn default() -> Self {
+        Self::new(vec![])
+    }
+}
+
+impl<W: Word> From<Vec<W>> for Memory<W> {
+    fn from(words: Vec<W>) -> Self {
+        Self::new(words)
+    }
+}
+
+/// Memories are equal when they hold the same words, whatever their storage or limit. Only
+/// pages allocated on either side are compared; the rest are zero on both.
+impl<W: Word> PartialEq for Memory<W> {
+    fn eq(&self, other: &Self) -> bool {
+        if self.len != other.len {
+            return false;
+        }
+
+        let pages: BTreeSet<_> = self
+            .chunks()
+            .into_iter()
+            .chain(other.chunks())
+            .map(|(idx, _)| idx)
+            .collect();
+        pages.into_iter().all(|idx| {
+            let start = idx * PAGE_SIZE;
+            let end = (start + PAGE_SIZE).min(self.len);
+            (start..end).all(|addr| self.get(addr) == other.get(addr))
+        })
+    }
+}
+
+impl<W: Word + Eq> Eq for Memory<W> {}
+
+impl<W: Word> fmt::Debug for Memory<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = if self.is_dense() { "dense" } else { "paged" };
+        f.debug_struct("Memory")
+            .field("kind", &kind)
+            .field("len", &self.len)
+            .field("limit", &self.limit)
+            .field("pages", &self.pages())
+            .field("shared_pages", &self.shared_pages())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_writes() {
+        let mut memory: Memory = Memory::new(vec![1, 2, 3]);
+        assert_eq!(3, memory.len());
+        assert_eq!(2, memory.get(1));
+        assert_eq!(0, memory.get(1_000_000));
+
+        memory.set(1_000_000, 7);
+        assert_eq!(7, memory.get(1_000_000));
+        assert_eq!(1_000_001, memory.len());
+        assert_eq!(2, memory.pages());
+    }
+
+    #[test]
+    fn clones_share_pages_until_written() {
+        let original: Memory = Memory::new((0..1000).collect());
+        let mut clone = original.clone();
+        assert_eq!(original.pages(), clone.shared_pages());
+
+        clone.set(5, -1);
+        assert_eq!(original.pages() - 1, clone.shared_pages());
+        assert_eq!(5, original.get(5));
+        assert_eq!(-1, clone.get(5));
+        assert_ne!(original, clone);
+
+        clone.set(5, 5);
+        assert_eq!(original, clone);
+    }
+
+    #[test]
+    fn dense_and_paged_agree() {
+        let mut paged: Memory = Memory::new(vec![1, 2, 3]);
+        let mut dense: Memory = Memory::dense(vec![1, 2, 3]);
+        for (addr, val) in &[(0, 9), (300, 4), (129, -2)] {
+            paged.set(*addr, *val);
+            dense.set(*addr, *val);
+        }
+
+        assert_eq!(paged, dense);
+        assert_eq!(paged.to_vec(), dense.to_vec());
+        assert_eq!(paged.chunks(), dense.chunks());
+        assert_eq!(301, dense.len());
+        assert_eq!(0, dense.pages());
+    }
+
+    #[test]
+    fn load_keeps_kind_and_limit() {
+        let mut memory: Memory = Memory::dense(vec![]).with_limit(10);
+        memory.load(vec![4, 5]);
+        assert!(memory.is_dense());
+        assert_eq!(Some(10), memory.limit());
+        assert!(memory.in_bounds(9));
+        assert!(!memory.in_bounds(10));
+        assert_eq!(Some(vec![4, 5]), memory.to_vec());
+    }
+
+    #[test]
+    fn huge_memories_stay_cheap() {
+        let mut memory: Memory = Memory::new(vec![1, 2, 3]);
+        memory.set(1_000_000_000_000, 7);
+        let mut other = memory.clone();
+        assert_eq!(memory, other);
+        other.set(999_999_999_999, 0);
+        assert_eq!(memory, other);
+        other.set(5, 1);
+        assert_ne!(memory, other);
+
+        assert_eq!(None, memory.to_vec());
+        let chunks = memory.chunks();
+        assert_eq!(2, chunks.len());
+        assert_eq!(&[1, 2, 3, 0][..], &chunks[0].1[..4]);
+        assert_eq!(
+            (1_000_000_000_000 / PAGE_SIZE, 1),
+            (chunks[1].0, chunks[1].1.len())
+        );
+
+        memory.set(MAX_ADDRESS, 1);
+        assert_eq!(usize::MAX, memory.len());
+    }
+
+    #[test]
+    fn loads_pages() {
+        let mut memory: Memory = Memory::dense(vec![9; 10]).with_limit(1000);
+        assert!(memory.load_pages(300, vec![(2, vec![4, 5])]));
+        assert!(memory.is_dense());
+        assert_eq!(Some(1000), memory.limit());
+        assert_eq!(300, memory.len());
+        assert_eq!(0, memory.get(0));
+        assert_eq!(5, memory.get(257));
+
+        let mut paged: Memory = Memory::new(vec![]);
+        assert!(paged.load_pages(300, vec![(2, vec![4, 5])]));
+        assert_eq!(memory, paged);
+        assert_eq!(1, paged.pages());
+
+        assert!(!memory.load_pages(1001, vec![]));
+        assert!(!memory.load_pages(300, vec![(3, vec![1; 45])]));
+        assert!(!paged.load_pages(1 << 40, vec![(usize::MAX, vec![1])]));
+        assert_eq!(5, memory.get(257));
+        assert_eq!(300, paged.len());
+    }
+}
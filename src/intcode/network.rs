@@ -0,0 +1,452 @@
+//! Runs a graph of machines whose outputs feed each other's inputs.
+//!
+//! Every output a node produces is delivered to each node it is connected to, so chains, rings,
+//! broadcasts and fan-ins are all just sets of edges. Nodes are scheduled round-robin on the
+//! calling thread, each running until it blocks on input, halts or uses up its slice of steps,
+//! which makes runs deterministic and lets a stalled graph be diagnosed rather than hang. A node
+//! that never blocks, e.g. one that outputs forever, is stopped once it reaches the step limit.
+
+use std::fmt;
+
+use super::budget::{Budget, Outcome};
+use super::{Computer, IntcodeError, ReturnMode};
+
+pub type NodeId = usize;
+
+/// Steps a node may take on each turn before the next node gets to run.
+const SLICE: u64 = 10_000;
+
+/// Steps each node may take in one `run`, unless set with `Network::step_limit`.
+pub const DEFAULT_STEP_LIMIT: u64 = 10_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeState {
+    /// Can still make progress, or has never been run.
+    Runnable,
+    /// Blocked on an input instruction at `inst_ptr` with nothing queued.
+    Waiting {
+        inst_ptr: usize,
+    },
+    Halted,
+    Faulted(IntcodeError),
+    /// Used up the step limit without blocking or halting, stopped before `inst_ptr`.
+    OutOfSteps {
+        inst_ptr: usize,
+    },
+}
+
+impl NodeState {
+    fn is_finished(self) -> bool {
+        matches!(self, NodeState::Halted | NodeState::Faulted(_))
+    }
+}
+
+struct Node {
+    computer: Computer,
+    targets: Vec<NodeId>,
+    outputs: Vec<i64>,
+    state: NodeState,
+}
+
+pub struct Network {
+    nodes: Vec<Node>,
+    step_limit: u64,
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self {
+            nodes: vec![],
+            step_limit: DEFAULT_STEP_LIMIT,
+        }
+    }
+}
+
+impl Network {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stops each node once it has taken `steps` steps in a single `run`.
+    pub fn step_limit(&mut self, steps: u64) -> &mut Self {
+        self.step_limit = steps;
+        self
+    }
+
+    pub fn add_node(&mut self, computer: Computer) -> NodeId {
+        self.nodes.push(Node {
+            computer,
+            targets: vec![],
+            outputs: vec![],
+            state: NodeState::Runnable,
+        });
+        self.nodes.len() - 1
+    }
+
+    /// Adds `count` nodes running copies of `program`.
+    pub fn add_nodes(&mut self, program: &[i64], count: usize) -> Vec<NodeId> {
+        let computer = Computer::new(program.to_vec());
+        (0..count).map(|_| self.add_node(computer.fork())).collect()
+    }
+
+    /// Delivers every output of `from` to the input of `to`.
+    pub fn connect(&mut self, from: NodeId, to: NodeId) -> &mut Self {
+        self.nodes[from].targets.push(to);
+        self
+    }
+
+    /// Connects each node to the next.
+    pub fn chain(&mut self, nodes: &[NodeId]) -> &mut Self {
+        for pair in nodes.windows(2) {
+            self.connect(pair[0], pair[1]);
+        }
+        self
+    }
+
+    /// Connects each node to the next, and the last back to the first.
+    pub fn ring(&mut self, nodes: &[NodeId]) -> &mut Self {
+        self.chain(nodes);
+        if let (Some(&last), Some(&first)) = (nodes.last(), nodes.first()) {
+            self.connect(last, first);
+        }
+        self
+    }
+
+    pub fn broadcast(&mut self, from: NodeId, to: &[NodeId]) -> &mut Self {
+        for target in to {
+            self.connect(from, *target);
+        }
+        self
+    }
+
+    /// Queues an initial input, such as a phase setting, for `node`.
+    pub fn seed(&mut self, node: NodeId, val: i64) -> &mut Self {
+        self.nodes[node].computer.push_input(val);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn computer(&self, node: NodeId) -> &Computer {
+        &self.nodes[node].computer
+    }
+
+    pub fn state(&self, node: NodeId) -> NodeState {
+        self.nodes[node].state
+    }
+
+    /// Every value `node` has output so far.
+    pub fn outputs(&self, node: NodeId) -> &[i64] {
+        &self.nodes[node].outputs
+    }
+
+    /// The nodes whose outputs feed `node`.
+    pub fn sources(&self, node: NodeId) -> Vec<NodeId> {
+        (0..self.nodes.len())
+            .filter(|id| self.nodes[*id].targets.contains(&node))
+            .collect()
+    }
+
+    /// Runs until every node has halted, faulted or reached the step limit, or no node can make
+    /// progress. Outputs sent to a finished node are dropped. Running again after seeding more
+    /// input resumes the graph, with nodes that reached the step limit getting a fresh one.
+    pub fn run(&mut self) -> Report {
+        for node in &mut self.nodes {
+            if let NodeState::OutOfSteps { .. } = node.state {
+                node.state = NodeState::Runnable;
+            }
+        }
+
+        let mut used = vec![0; self.nodes.len()];
+        loop {
+            let mut progressed = false;
+            for (id, used) in used.iter_mut().enumerate() {
+                if self.is_runnable(id) {
+                    *used += self.run_node(id, self.step_limit - *used);
+                    progressed = true;
+                }
+            }
+
+            if !progressed {
+                return self.report();
+            }
+        }
+    }
+
+    fn is_runnable(&self, id: NodeId) -> bool {
+        let node = &self.nodes[id];
+        match node.state {
+            NodeState::Runnable => true,
+            NodeState::Waiting { .. } => node.computer.pending_inputs() > 0,
+            NodeState::Halted | NodeState::Faulted(_) | NodeState::OutOfSteps { .. } => false,
+        }
+    }
+
+    /// Gives `id` one turn of at most a slice of its `remaining` steps, returning the steps used.
+    fn run_node(&mut self, id: NodeId, remaining: u64) -> u64 {
+        let mut budget = Budget::new().steps(SLICE.min(remaining));
+        loop {
+            let node = &mut self.nodes[id];
+            let val = match node.computer.run_budgeted(&mut budget) {
+                Ok(Outcome::Returned(ReturnMode::Output(val))) => val,
+                Ok(Outcome::Returned(ReturnMode::NeedsInput)) => {
+                    node.state = NodeState::Waiting {
+                        inst_ptr: node.computer.inst_ptr(),
+                    };
+                    return budget.used();
+                }
+                Ok(Outcome::Returned(ReturnMode::Halt)) => {
+                    node.state = NodeState::Halted;
+                    return budget.used();
+                }
+                Ok(Outcome::BudgetExhausted(_)) => {
+                    if budget.used() == remaining {
+                        node.state = NodeState::OutOfSteps {
+                            inst_ptr: node.computer.inst_ptr(),
+                        };
+                    }
+                    return budget.used();
+                }
+                Err(e) => {
+                    node.state = NodeState::Faulted(e);
+                    return budget.used();
+                }
+            };
+
+            node.outputs.push(val);
+            for target in node.targets.clone() {
+                let target = &mut self.nodes[target];
+                if !target.state.is_finished() {
+                    target.computer.push_input(val);
+                }
+            }
+        }
+    }
+
+    fn report(&self) -> Report {
+        let waiting = (0..self.nodes.len())
+            .filter_map(|id| match self.nodes[id].state {
+                NodeState::Waiting { inst_ptr } => Some(Waiting {
+                    node: id,
+                    inst_ptr,
+                    sources: self
+                        .sources(id)
+                        .into_iter()
+                        .map(|source| (source, self.nodes[source].state))
+                        .collect(),
+                }),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        Report {
+            states: self.nodes.iter().map(|node| node.state).collect(),
+            outputs: self.nodes.iter().map(|node| node.outputs.clone()).collect(),
+            deadlock: if waiting.is_empty() {
+                None
+            } else {
+                Some(Deadlock { waiting })
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub states: Vec<NodeState>,
+    /// Every value each node output, indexed by `NodeId`.
+    pub outputs: Vec<Vec<i64>>,
+    /// Set when the run stopped with nodes still waiting for input.
+    pub deadlock: Option<Deadlock>,
+}
+
+impl Report {
+    /// Whether every node halted cleanly.
+    pub fn all_halted(&self) -> bool {
+        self.states.iter().all(|state| *state == NodeState::Halted)
+    }
+
+    pub fn last_output(&self, node: NodeId) -> Option<i64> {
+        self.outputs[node].last().copied()
+    }
+
+    /// Nodes stopped by the step limit, e.g. because they output forever or loop without
+    /// reading input.
+    pub fn out_of_steps(&self) -> Vec<NodeId> {
+        self.states
+            .iter()
+            .enumerate()
+            .filter(|(_, state)| matches!(state, NodeState::OutOfSteps { .. }))
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Nodes that faulted, with their errors.
+    pub fn faults(&self) -> Vec<(NodeId, IntcodeError)> {
+        self.states
+            .iter()
+            .enumerate()
+            .filter_map(|(id, state)| match state {
+                NodeState::Faulted(e) => Some((id, *e)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deadlock {
+    pub waiting: Vec<Waiting>,
+}
+
+/// A node blocked on input, and the state of every node that could have supplied it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Waiting {
+    pub node: NodeId,
+    pub inst_ptr: usize,
+    pub sources: Vec<(NodeId, NodeState)>,
+}
+
+impl fmt::Display for Deadlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "deadlock:")?;
+        for waiting in &self.waiting {
+            write!(
+                f,
+                "  node {} waiting for input at {}",
+                waiting.node, waiting.inst_ptr
+            )?;
+
+            if waiting.sources.is_empty() {
+                writeln!(f, " with nothing connected to it")?;
+                continue;
+            }
+
+            let sources: Vec<_> = waiting
+                .sources
+                .iter()
+                .map(|(id, state)| match state {
+                    NodeState::Runnable => format!("node {} (runnable)", id),
+                    NodeState::Waiting { .. } => format!("node {} (waiting)", id),
+                    NodeState::Halted => format!("node {} (halted)", id),
+                    NodeState::Faulted(e) => format!("node {} (faulted: {})", id, e),
+                    NodeState::OutOfSteps { .. } => format!("node {} (out of steps)", id),
+                })
+                .collect();
+            writeln!(f, " from {}", sources.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::read_input;
+    use crate::util;
+
+    // Adds one to every input, forever.
+    const INC: &[u8] = b"3,9,101,1,9,9,4,9,1105,1,0";
+
+    #[test]
+    fn amplifier_feedback_ring() {
+        let program = read_input(&util::read_input_file("day7.txt")[..]);
+        let mut network = Network::new();
+        let amps = network.add_nodes(&program, 5);
+        network.ring(&amps);
+        for (amp, phase) in amps.iter().zip(&[6, 7, 9, 8, 5]) {
+            network.seed(*amp, *phase);
+        }
+        network.seed(amps[0], 0);
+
+        let report = network.run();
+        assert!(report.all_halted(), "{:?}", report);
+        assert_eq!(Some(61379886), report.last_output(amps[4]));
+    }
+
+    #[test]
+    fn broadcast_and_fan_in() {
+        let program = read_input(INC);
+        let mut network = Network::new();
+        let nodes = network.add_nodes(&program, 4);
+        network
+            .broadcast(nodes[0], &nodes[1..3])
+            .connect(nodes[1], nodes[3])
+            .connect(nodes[2], nodes[3])
+            .seed(nodes[0], 10);
+
+        let report = network.run();
+        assert_eq!(vec![11], report.outputs[0]);
+        assert_eq!(vec![12], report.outputs[1]);
+        assert_eq!(vec![12], report.outputs[2]);
+        assert_eq!(vec![13, 13], report.outputs[3]);
+
+        let deadlock = report.deadlock.unwrap();
+        assert_eq!(4, deadlock.waiting.len());
+        assert_eq!(
+            Waiting {
+                node: 3,
+                inst_ptr: 0,
+                sources: vec![
+                    (1, NodeState::Waiting { inst_ptr: 0 }),
+                    (2, NodeState::Waiting { inst_ptr: 0 })
+                ],
+            },
+            deadlock.waiting[3]
+        );
+    }
+
+    #[test]
+    fn diagnoses_starved_nodes() {
+        let mut network = Network::new();
+        let producer = network.add_node(Computer::new(read_input(&b"104,1,99"[..])));
+        let consumer = network.add_node(Computer::new(read_input(&b"3,0,3,0,99"[..])));
+        network.connect(producer, consumer);
+
+        let report = network.run();
+        assert_eq!(
+            vec![NodeState::Halted, NodeState::Waiting { inst_ptr: 2 }],
+            report.states
+        );
+        assert_eq!(
+            "deadlock:\n  node 1 waiting for input at 2 from node 0 (halted)\n",
+            report.deadlock.unwrap().to_string()
+        );
+
+        network.seed(consumer, 5);
+        assert!(network.run().all_halted());
+    }
+
+    #[test]
+    fn stops_nodes_that_never_block() {
+        let mut network = Network::new();
+        let chatty = network.add_node(Computer::new(read_input(&b"104,1,1105,1,0"[..])));
+        let spinner = network.add_node(Computer::new(read_input(&b"1105,1,0"[..])));
+        let consumer = network.add_node(Computer::new(read_input(&b"3,0,4,0,99"[..])));
+        let starved = network.add_node(Computer::new(read_input(INC)));
+        network
+            .connect(chatty, consumer)
+            .connect(spinner, starved)
+            .step_limit(100_000);
+
+        let report = network.run();
+        assert_eq!(vec![chatty, spinner], report.out_of_steps());
+        assert_eq!(50_000, report.outputs[chatty].len());
+        assert_eq!(vec![1], report.outputs[consumer]);
+        assert_eq!(NodeState::Halted, report.states[consumer]);
+        assert_eq!(
+            "deadlock:\n  node 3 waiting for input at 0 from node 1 (out of steps)\n",
+            report.deadlock.unwrap().to_string()
+        );
+
+        let report = network.run();
+        assert_eq!(100_000, report.outputs[chatty].len());
+    }
+}
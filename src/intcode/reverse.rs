@@ -0,0 +1,168 @@
+//! An undo log for running programs backwards.
+//!
+//! `UndoLog` is a `Tracer` that keeps just enough of each executed instruction to reverse it:
+//! the registers before it ran, the memory cell it overwrote and any input it consumed. Undoing
+//! entries one at a time walks the machine back through its exact earlier states, with consumed
+//! input put back on the queue. Memory that grew stays allocated, but reads as zero again.
+
+use std::collections::VecDeque;
+
+use super::trace::{MemoryWrite, TraceEvent, Tracer};
+use super::{Computer, Op};
+
+/// Everything needed to undo one instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub inst_ptr: usize,
+    pub relative_base: i64,
+    pub write: Option<MemoryWrite>,
+    /// The input value the instruction consumed.
+    pub input: Option<i64>,
+    /// The value the instruction output.
+    pub output: Option<i64>,
+}
+
+impl Entry {
+    fn new(event: &TraceEvent) -> Self {
+        let input = match event.op {
+            Op::Input(_) => event.write.map(|write| write.new),
+            _ => None,
+        };
+        let output = match event.op {
+            Op::Output(_) => event.operands.first().map(|operand| operand.value),
+            _ => None,
+        };
+
+        Self {
+            inst_ptr: event.inst_ptr,
+            relative_base: event.relative_base,
+            write: event.write,
+            input,
+            output,
+        }
+    }
+}
+
+/// The most recent executed instructions, newest last.
+#[derive(Debug, Clone, Default)]
+pub struct UndoLog {
+    entries: VecDeque<Entry>,
+    limit: Option<usize>,
+}
+
+impl UndoLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps only the last `limit` instructions.
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            limit: Some(limit),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn last(&self) -> Option<&Entry> {
+        self.entries.back()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Reverses the most recent instruction on `computer`, which must be the machine the log
+    /// was recorded from, returning what was undone.
+    pub fn undo(&mut self, computer: &mut Computer) -> Option<Entry> {
+        let entry = self.entries.pop_back()?;
+
+        if let Some(write) = entry.write {
+            computer.poke(write.addr, write.old);
+        }
+        if let Some(val) = entry.input {
+            computer.inputs.push_front(val);
+        }
+        computer.inst_ptr = entry.inst_ptr;
+        computer.relative_base = entry.relative_base;
+
+        Some(entry)
+    }
+}
+
+impl Tracer for UndoLog {
+    fn trace(&mut self, event: &TraceEvent) {
+        match self.limit {
+            Some(0) => return,
+            Some(limit) if self.entries.len() == limit => {
+                self.entries.pop_front();
+            }
+            _ => {}
+        }
+        self.entries.push_back(Entry::new(event));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{read_input, ReturnMode};
+    use crate::util;
+
+    #[test]
+    fn undoing_retraces_every_state() {
+        let program = read_input(&util::read_input_file("day9.txt")[..]);
+        let mut computer = Computer::new(program);
+        computer.push_input(1);
+
+        let mut log = UndoLog::new();
+        let mut snapshots = vec![computer.snapshot()];
+        let mut outputs = vec![];
+        loop {
+            let mode = computer.step_traced(&mut log).unwrap();
+            snapshots.push(computer.snapshot());
+            match mode {
+                Some(ReturnMode::Output(val)) => outputs.push(val),
+                Some(ReturnMode::Halt) => break,
+                Some(ReturnMode::NeedsInput) => unreachable!(),
+                None => {}
+            }
+        }
+
+        assert_eq!(snapshots.len() - 1, log.len());
+        assert!(!outputs.is_empty());
+        while let Some(entry) = log.undo(&mut computer) {
+            if let Some(val) = entry.output {
+                assert_eq!(Some(val), outputs.pop());
+            }
+            snapshots.pop();
+            let mut expected = snapshots.last().unwrap().clone();
+            // Memory the program grew stays allocated.
+            expected.memory_len = computer.memory_len();
+            assert_eq!(expected, computer.snapshot());
+        }
+        assert!(outputs.is_empty());
+    }
+
+    #[test]
+    fn keeps_only_the_newest_entries() {
+        let mut computer = Computer::new(read_input(&b"1105,1,0"[..]));
+        let mut log = UndoLog::with_limit(3);
+        for _ in 0..10 {
+            computer.step_traced(&mut log).unwrap();
+        }
+
+        assert_eq!(3, log.len());
+        for _ in 0..3 {
+            assert!(log.undo(&mut computer).is_some());
+        }
+        assert!(log.undo(&mut computer).is_none());
+    }
+}
@@ -0,0 +1,457 @@
+//! Serializable machine state, for pausing a `Computer` and resuming it elsewhere.
+//!
+//! Memory is stored a page at a time, with pages that are all zero left out, so a sparse machine
+//! that wrote to a huge address costs a few pages rather than everything below it. The binary
+//! format is little-endian:
+//!
+//! ```text
+//! magic          b"ICSN"
+//! version        u32
+//! inst_ptr       u64
+//! relative_base  i64
+//! memory_len     u64
+//! page_count     u64, followed by that many pages:
+//!   index        u64
+//!   words_len    u64 (at most PAGE_SIZE), followed by that many i64 words
+//! inputs_len     u64, followed by that many i64 words
+//! ```
+//!
+//! The JSON variant carries the same fields plus a `version` number, with each page as an
+//! `[index, [words]]` pair. Version 1 snapshots, which stored memory as a single list of words,
+//! can still be read.
+
+use std::error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use super::memory::{Memory, PAGE_SIZE};
+
+const MAGIC: &[u8; 4] = b"ICSN";
+pub const VERSION: u32 = 2;
+
+/// Everything needed to resume a machine: memory, registers and any queued input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// One past the highest address that has been loaded or written.
+    pub memory_len: usize,
+    /// The pages of memory that aren't all zero, as (page index, words), in address order.
+    /// Words missing from the end of a page are zero.
+    pub pages: Vec<(usize, Vec<i64>)>,
+    pub inst_ptr: usize,
+    pub relative_base: i64,
+    pub inputs: Vec<i64>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    BadMagic,
+    UnsupportedVersion(u32),
+    /// A length or pointer that does not fit in this platform's address space.
+    TooLarge(u64),
+    /// A page that is out of order, too long or past the end of memory.
+    BadPage(u64),
+    /// Memory of this many words is past the restoring machine's limit.
+    DoesNotFit(usize),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "{}", e),
+            SnapshotError::Json(e) => write!(f, "invalid snapshot JSON: {}", e),
+            SnapshotError::BadMagic => write!(f, "not an Intcode snapshot"),
+            SnapshotError::UnsupportedVersion(v) => {
+                write!(f, "unsupported snapshot version {}", v)
+            }
+            SnapshotError::TooLarge(n) => write!(f, "snapshot value {} is too large", n),
+            SnapshotError::BadPage(idx) => write!(f, "invalid snapshot page {}", idx),
+            SnapshotError::DoesNotFit(len) => {
+                write!(
+                    f,
+                    "snapshot memory of {} words doesn't fit the machine",
+                    len
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SnapshotError::Io(e) => Some(e),
+            SnapshotError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(e: serde_json::Error) -> Self {
+        SnapshotError::Json(e)
+    }
+}
+
+#[derive(Serialize)]
+struct JsonOut<'a> {
+    version: u32,
+    inst_ptr: usize,
+    relative_base: i64,
+    memory_len: usize,
+    pages: &'a [(usize, Vec<i64>)],
+    inputs: &'a [i64],
+}
+
+#[derive(Deserialize)]
+struct JsonIn {
+    version: u32,
+    inst_ptr: usize,
+    relative_base: i64,
+    /// Version 1 only.
+    #[serde(default)]
+    memory: Vec<i64>,
+    #[serde(default)]
+    memory_len: usize,
+    #[serde(default)]
+    pages: Vec<(usize, Vec<i64>)>,
+    inputs: Vec<i64>,
+}
+
+impl Snapshot {
+    /// Captures `memory`, leaving out trailing zeros and pages that are all zero, so equal
+    /// memories give equal pages.
+    pub(crate) fn pages_of(memory: &Memory) -> Vec<(usize, Vec<i64>)> {
+        memory
+            .chunks()
+            .into_iter()
+            .filter_map(|(idx, words)| {
+                let used = words.iter().rposition(|word| *word != 0)? + 1;
+                Some((idx, words[..used].to_vec()))
+            })
+            .collect()
+    }
+
+    /// The pages of a version 1 snapshot's flat memory.
+    fn paginate(words: &[i64]) -> Vec<(usize, Vec<i64>)> {
+        Self::pages_of(&Memory::new(words.to_vec()))
+    }
+
+    /// Checks that the pages are in order and fit in `memory_len`, as written by `pages_of`.
+    fn validate(&self) -> Result<(), SnapshotError> {
+        let mut next = 0;
+        for (idx, words) in &self.pages {
+            let start = idx.checked_mul(PAGE_SIZE);
+            let end = start.and_then(|start| start.checked_add(words.len()));
+            match end {
+                Some(end) if *idx >= next && words.len() <= PAGE_SIZE && end <= self.memory_len => {
+                    next = idx + 1;
+                }
+                _ => return Err(SnapshotError::BadPage(*idx as u64)),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&(self.inst_ptr as u64).to_le_bytes())?;
+        w.write_all(&self.relative_base.to_le_bytes())?;
+        w.write_all(&(self.memory_len as u64).to_le_bytes())?;
+        w.write_all(&(self.pages.len() as u64).to_le_bytes())?;
+        for (idx, words) in &self.pages {
+            w.write_all(&(*idx as u64).to_le_bytes())?;
+            write_words(&mut w, words)?;
+        }
+        write_words(&mut w, &self.inputs)
+    }
+
+    pub fn read_from(mut r: impl Read) -> Result<Self, SnapshotError> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+
+        let mut version = [0; 4];
+        r.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != 1 && version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let inst_ptr = read_usize(&mut r)?;
+        let relative_base = read_i64(&mut r)?;
+        let (memory_len, pages) = if version == 1 {
+            let words = read_words(&mut r)?;
+            (words.len(), Self::paginate(&words))
+        } else {
+            let memory_len = read_usize(&mut r)?;
+            let count = read_usize(&mut r)?;
+            let mut pages = Vec::with_capacity(count.min(1 << 16));
+            for _ in 0..count {
+                let idx = read_usize(&mut r)?;
+                pages.push((idx, read_words(&mut r)?));
+            }
+            (memory_len, pages)
+        };
+        let inputs = read_words(&mut r)?;
+
+        let snapshot = Self {
+            memory_len,
+            pages,
+            inst_ptr,
+            relative_base,
+            inputs,
+        };
+        snapshot.validate()?;
+        Ok(snapshot)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let words: usize = self.pages.iter().map(|(_, words)| 2 + words.len()).sum();
+        let mut buf = Vec::with_capacity(48 + 8 * (words + self.inputs.len()));
+        self.write_to(&mut buf)
+            .expect("writing to a Vec cannot fail");
+        buf
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, SnapshotError> {
+        Self::read_from(&mut bytes)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&JsonOut {
+            version: VERSION,
+            inst_ptr: self.inst_ptr,
+            relative_base: self.relative_base,
+            memory_len: self.memory_len,
+            pages: &self.pages,
+            inputs: &self.inputs,
+        })
+        .expect("snapshots always serialize")
+    }
+
+    pub fn from_json(raw: &str) -> Result<Self, SnapshotError> {
+        let json: JsonIn = serde_json::from_str(raw)?;
+        let (memory_len, pages) = match json.version {
+            1 => (json.memory.len(), Self::paginate(&json.memory)),
+            VERSION => (json.memory_len, json.pages),
+            version => return Err(SnapshotError::UnsupportedVersion(version)),
+        };
+
+        let snapshot = Self {
+            memory_len,
+            pages,
+            inst_ptr: json.inst_ptr,
+            relative_base: json.relative_base,
+            inputs: json.inputs,
+        };
+        snapshot.validate()?;
+        Ok(snapshot)
+    }
+}
+
+fn write_words(w: &mut impl Write, words: &[i64]) -> io::Result<()> {
+    w.write_all(&(words.len() as u64).to_le_bytes())?;
+    for word in words {
+        w.write_all(&word.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_i64(r: &mut impl Read) -> io::Result<i64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(i64::from_le_bytes(buf))
+}
+
+fn read_usize(r: &mut impl Read) -> Result<usize, SnapshotError> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    let val = u64::from_le_bytes(buf);
+    if val > usize::MAX as u64 {
+        return Err(SnapshotError::TooLarge(val));
+    }
+    Ok(val as usize)
+}
+
+fn read_words(r: &mut impl Read) -> Result<Vec<i64>, SnapshotError> {
+    let len = read_usize(r)?;
+
+    // Don't trust the length for the allocation; a corrupt header would otherwise abort.
+    let mut words = Vec::with_capacity(len.min(1 << 16));
+    for _ in 0..len {
+        words.push(read_i64(r)?);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::memory::Memory;
+    use crate::intcode::{Computer, ReturnMode};
+    use std::convert::TryFrom;
+
+    // Echoes one input, then waits for the next.
+    const ECHO: &[i64] = &[3, 100, 4, 100, 1105, 1, 0];
+
+    fn paused() -> Computer {
+        let mut computer = Computer::new(ECHO.to_vec());
+        computer.push_input(7);
+        computer.push_input(8);
+        computer.push_input(9);
+        assert_eq!(ReturnMode::Output(7), computer.run_program().unwrap());
+        computer
+    }
+
+    #[test]
+    fn restores_a_paused_machine() {
+        let mut original = paused();
+        let snapshot = original.snapshot();
+        assert_eq!(vec![8, 9], snapshot.inputs);
+        assert_eq!(4, snapshot.inst_ptr);
+
+        let mut resumed = Computer::try_from(snapshot.clone()).unwrap();
+        assert_eq!(ReturnMode::Output(8), original.run_program().unwrap());
+        assert_eq!(ReturnMode::Output(8), resumed.run_program().unwrap());
+
+        original.restore(&snapshot).unwrap();
+        assert_eq!(snapshot, original.snapshot());
+        assert_eq!(ReturnMode::Output(8), original.run_program().unwrap());
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let snapshot = paused().snapshot();
+        let bytes = snapshot.to_bytes();
+        assert_eq!(b"ICSN", &bytes[..4]);
+        assert_eq!(snapshot, Snapshot::from_bytes(&bytes).unwrap());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let snapshot = Computer::new(vec![1, 0, 0, 0, 99]).snapshot();
+        let json = snapshot.to_json();
+        assert_eq!(
+            r#"{"version":2,"inst_ptr":0,"relative_base":0,"memory_len":5,"pages":[[0,[1,0,0,0,99]]],"inputs":[]}"#,
+            json
+        );
+        assert_eq!(snapshot, Snapshot::from_json(&json).unwrap());
+    }
+
+    #[test]
+    fn stores_only_used_pages() {
+        let mut computer = Computer::new(vec![1101, 1, 2, 1_000_000_000_000, 99]);
+        computer.run_to_halt().unwrap();
+        let snapshot = computer.snapshot();
+        assert_eq!(1_000_000_000_001, snapshot.memory_len);
+        assert_eq!(2, snapshot.pages.len());
+
+        let bytes = snapshot.to_bytes();
+        assert!(bytes.len() < 2 * PAGE_SIZE * 8);
+        let restored = Computer::try_from(Snapshot::from_bytes(&bytes).unwrap()).unwrap();
+        assert_eq!(3, restored.peek(1_000_000_000_000));
+        assert_eq!(computer.memory(), restored.memory());
+        assert_eq!(snapshot, Snapshot::from_json(&snapshot.to_json()).unwrap());
+    }
+
+    #[test]
+    fn reads_version_1() {
+        let json =
+            r#"{"version":1,"inst_ptr":4,"relative_base":0,"memory":[1,0,0,0,99],"inputs":[7]}"#;
+        let snapshot = Snapshot::from_json(json).unwrap();
+        assert_eq!(
+            Computer::new(vec![1, 0, 0, 0, 99]).snapshot().pages,
+            snapshot.pages
+        );
+        assert_eq!(5, snapshot.memory_len);
+
+        let mut bytes = b"ICSN".to_vec();
+        bytes.extend(&1u32.to_le_bytes());
+        for word in &[4u64, 0, 2, 99, 0, 1, 7] {
+            bytes.extend(&word.to_le_bytes());
+        }
+        let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+        assert_eq!(vec![(0, vec![99])], snapshot.pages);
+        assert_eq!(2, snapshot.memory_len);
+        assert_eq!(vec![7], snapshot.inputs);
+    }
+
+    #[test]
+    fn refuses_memory_past_the_limit() {
+        let hostile = Snapshot::from_json(
+            r#"{"version":2,"inst_ptr":0,"relative_base":0,"memory_len":1099511627776,"pages":[],"inputs":[]}"#,
+        )
+        .unwrap();
+
+        let mut computer = Computer::with_memory(Memory::dense(vec![99]).with_limit(100));
+        assert!(matches!(
+            computer.restore(&hostile),
+            Err(SnapshotError::DoesNotFit(1099511627776))
+        ));
+        assert_eq!(1, computer.memory_len());
+        assert_eq!(Vec::<i64>::new(), computer.run_to_halt().unwrap());
+
+        let mut dense = Computer::with_memory(Memory::dense(vec![99]));
+        assert!(dense.restore(&hostile).is_err());
+
+        // Paged memory without a limit only allocates the pages that were saved.
+        let restored = Computer::try_from(hostile).unwrap();
+        assert_eq!(1099511627776, restored.memory_len());
+        assert_eq!(0, restored.memory().pages());
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let mut bytes = paused().snapshot().to_bytes();
+
+        assert!(matches!(
+            Snapshot::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(SnapshotError::Io(_))
+        ));
+
+        bytes[4] = 3;
+        assert!(matches!(
+            Snapshot::from_bytes(&bytes),
+            Err(SnapshotError::UnsupportedVersion(3))
+        ));
+
+        bytes[0] = b'X';
+        assert!(matches!(
+            Snapshot::from_bytes(&bytes),
+            Err(SnapshotError::BadMagic)
+        ));
+
+        assert!(matches!(
+            Snapshot::from_json(
+                r#"{"version":9,"inst_ptr":0,"relative_base":0,"memory":[],"inputs":[]}"#
+            ),
+            Err(SnapshotError::UnsupportedVersion(9))
+        ));
+
+        assert!(matches!(
+            Snapshot::from_json(
+                r#"{"version":2,"inst_ptr":0,"relative_base":0,"memory_len":200,"pages":[[1,[1]],[0,[2]]],"inputs":[]}"#
+            ),
+            Err(SnapshotError::BadPage(0))
+        ));
+        assert!(matches!(
+            Snapshot::from_json(
+                r#"{"version":2,"inst_ptr":0,"relative_base":0,"memory_len":2,"pages":[[0,[1,2,3]]],"inputs":[]}"#
+            ),
+            Err(SnapshotError::BadPage(0))
+        ));
+    }
+}
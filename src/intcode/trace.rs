@@ -0,0 +1,381 @@
+//! Opt-in instruction tracing for `Computer::run_traced`, plus a profiler and a JSON Lines writer
+//! built on top of it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use super::Op;
+
+/// A parameter the instruction read, with the address it came from unless it was immediate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Operand {
+    pub address: Option<usize>,
+    pub value: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MemoryWrite {
+    pub addr: usize,
+    pub old: i64,
+    pub new: i64,
+}
+
+/// One executed instruction. `relative_base` is the value before the instruction ran.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEvent {
+    pub inst_ptr: usize,
+    pub relative_base: i64,
+    pub op: Op,
+    pub operands: Vec<Operand>,
+    pub write: Option<MemoryWrite>,
+    pub next_inst_ptr: usize,
+}
+
+pub trait Tracer {
+    fn trace(&mut self, event: &TraceEvent);
+}
+
+impl Tracer for Vec<TraceEvent> {
+    fn trace(&mut self, event: &TraceEvent) {
+        self.push(event.clone());
+    }
+}
+
+impl<A: Tracer, B: Tracer> Tracer for (A, B) {
+    fn trace(&mut self, event: &TraceEvent) {
+        self.0.trace(event);
+        self.1.trace(event);
+    }
+}
+
+impl<T: Tracer + ?Sized> Tracer for &mut T {
+    fn trace(&mut self, event: &TraceEvent) {
+        (**self).trace(event);
+    }
+}
+
+/// Index of the first event at which two traces differ, or the length of the shorter trace if
+/// one is a prefix of the other. `None` if they are identical.
+pub fn first_divergence(a: &[TraceEvent], b: &[TraceEvent]) -> Option<usize> {
+    match a.iter().zip(b).position(|(a, b)| a != b) {
+        Some(idx) => Some(idx),
+        None if a.len() != b.len() => Some(a.len().min(b.len())),
+        None => None,
+    }
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    inst_ptr: usize,
+    relative_base: i64,
+    op: String,
+    operands: &'a [Operand],
+    write: Option<MemoryWrite>,
+    next_inst_ptr: usize,
+}
+
+/// Writes each event as one JSON object per line.
+pub struct JsonLines<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> JsonLines<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            error: None,
+        }
+    }
+
+    /// Flushes the writer and returns it, or the first error hit while tracing.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_event(&mut self, event: &TraceEvent) -> io::Result<()> {
+        let record = Record {
+            inst_ptr: event.inst_ptr,
+            relative_base: event.relative_base,
+            op: event.op.to_string(),
+            operands: &event.operands,
+            write: event.write,
+            next_inst_ptr: event.next_inst_ptr,
+        };
+
+        serde_json::to_writer(&mut self.writer, &record)?;
+        writeln!(self.writer)
+    }
+}
+
+impl<W: Write> Tracer for JsonLines<W> {
+    fn trace(&mut self, event: &TraceEvent) {
+        if self.error.is_none() {
+            self.error = self.write_event(event).err();
+        }
+    }
+}
+
+/// A backward jump that was taken, and the work done inside the range it spans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HotLoop {
+    pub start: usize,
+    pub end: usize,
+    pub iterations: u64,
+    pub instructions: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    total: u64,
+    by_address: BTreeMap<usize, u64>,
+    by_opcode: BTreeMap<&'static str, u64>,
+    back_edges: HashMap<(usize, usize), u64>,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of instructions executed.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn by_address(&self) -> &BTreeMap<usize, u64> {
+        &self.by_address
+    }
+
+    /// Execution counts keyed by mnemonic.
+    pub fn by_opcode(&self) -> &BTreeMap<&'static str, u64> {
+        &self.by_opcode
+    }
+
+    /// Loops ordered by the number of instructions executed inside them, busiest first.
+    pub fn hot_loops(&self) -> Vec<HotLoop> {
+        let mut loops: Vec<_> = self
+            .back_edges
+            .iter()
+            .map(|(&(end, start), &iterations)| HotLoop {
+                start,
+                end,
+                iterations,
+                instructions: self.by_address.range(start..=end).map(|(_, n)| n).sum(),
+            })
+            .collect();
+
+        loops.sort_by(|a, b| {
+            b.instructions
+                .cmp(&a.instructions)
+                .then(a.start.cmp(&b.start))
+                .then(a.end.cmp(&b.end))
+        });
+        loops
+    }
+}
+
+impl Tracer for Profile {
+    fn trace(&mut self, event: &TraceEvent) {
+        self.total += 1;
+        *self.by_address.entry(event.inst_ptr).or_insert(0) += 1;
+        *self.by_opcode.entry(event.op.mnemonic()).or_insert(0) += 1;
+
+        let jumped = event.next_inst_ptr != event.inst_ptr + event.op.size();
+        if event.op != Op::Halt && jumped && event.next_inst_ptr <= event.inst_ptr {
+            *self
+                .back_edges
+                .entry((event.inst_ptr, event.next_inst_ptr))
+                .or_insert(0) += 1;
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} instructions executed", self.total)?;
+
+        writeln!(f, "\nby opcode:")?;
+        let mut opcodes: Vec<_> = self.by_opcode.iter().collect();
+        opcodes.sort_by(|a, b| b.1.cmp(a.1));
+        for (mnemonic, count) in opcodes {
+            writeln!(f, "  {:<4} {:>12}", mnemonic, count)?;
+        }
+
+        writeln!(f, "\nhottest addresses:")?;
+        let mut addresses: Vec<_> = self.by_address.iter().collect();
+        addresses.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (addr, count) in addresses.iter().take(10) {
+            writeln!(f, "  {:>6} {:>12}", addr, count)?;
+        }
+
+        writeln!(f, "\nhot loops:")?;
+        for l in self.hot_loops().iter().take(10) {
+            writeln!(
+                f,
+                "  {:>6}..={:<6} {:>10} iterations {:>12} instructions",
+                l.start, l.end, l.iterations, l.instructions
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+    use crate::intcode::{Computer, ReturnMode};
+
+    const COUNTDOWN: &str = "
+                in [n]
+        loop:   out [n]
+                add [n], -1, [n]
+                jt [n], loop
+                hlt
+        n:      data 0
+    ";
+
+    fn countdown(from: i64) -> Computer {
+        let mut computer = Computer::new(assemble(COUNTDOWN).unwrap());
+        computer.push_input(from);
+        computer
+    }
+
+    #[test]
+    fn faults_only_where_step_does() {
+        // An untaken jump never reads its bad target.
+        let mut computer = Computer::new(vec![2006, 0, -1, 99]);
+        let mut events = vec![];
+        assert_eq!(ReturnMode::Halt, computer.run_traced(&mut events).unwrap());
+        assert_eq!(1, events[0].operands.len());
+
+        // Blocking on input comes before the bad destination is resolved.
+        let mut computer = Computer::new(vec![203, -1, 99]);
+        assert_eq!(
+            ReturnMode::NeedsInput,
+            computer.run_traced(&mut events).unwrap()
+        );
+    }
+
+    #[test]
+    fn records_operands_and_writes() {
+        let mut computer = countdown(2);
+        let mut events = vec![];
+
+        assert_eq!(
+            ReturnMode::Output(2),
+            computer.run_traced(&mut events).unwrap()
+        );
+        assert_eq!(2, events.len());
+
+        assert_eq!(
+            TraceEvent {
+                inst_ptr: 0,
+                relative_base: 0,
+                op: Op::Input((crate::intcode::ParamMode::Position, 12)),
+                operands: vec![],
+                write: Some(MemoryWrite {
+                    addr: 12,
+                    old: 0,
+                    new: 2
+                }),
+                next_inst_ptr: 2,
+            },
+            events[0]
+        );
+        assert_eq!(
+            vec![Operand {
+                address: Some(12),
+                value: 2
+            }],
+            events[1].operands
+        );
+
+        computer.run_traced(&mut events).unwrap();
+        let add = &events[2];
+        assert_eq!(
+            vec![
+                Operand {
+                    address: Some(12),
+                    value: 2
+                },
+                Operand {
+                    address: None,
+                    value: -1
+                }
+            ],
+            add.operands
+        );
+        assert_eq!(
+            Some(MemoryWrite {
+                addr: 12,
+                old: 2,
+                new: 1
+            }),
+            add.write
+        );
+    }
+
+    #[test]
+    fn profiles_loops() {
+        let mut computer = countdown(10);
+        let mut profile = Profile::new();
+        while computer.run_traced(&mut profile).unwrap() != ReturnMode::Halt {}
+
+        // in, 10 * (out, add, jt), hlt
+        assert_eq!(32, profile.total());
+        assert_eq!(Some(&10), profile.by_opcode().get("add"));
+        assert_eq!(Some(&10), profile.by_address().get(&8));
+
+        assert_eq!(
+            vec![HotLoop {
+                start: 2,
+                end: 8,
+                iterations: 9,
+                instructions: 30
+            }],
+            profile.hot_loops()
+        );
+    }
+
+    #[test]
+    fn writes_json_lines() {
+        let mut computer = countdown(1);
+        let mut json = JsonLines::new(vec![]);
+        computer.run_traced(&mut json).unwrap();
+
+        let out = String::from_utf8(json.finish().unwrap()).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(
+            vec![
+                r#"{"inst_ptr":0,"relative_base":0,"op":"in [12]","operands":[],"write":{"addr":12,"old":0,"new":1},"next_inst_ptr":2}"#,
+                r#"{"inst_ptr":2,"relative_base":0,"op":"out [12]","operands":[{"address":12,"value":1}],"write":null,"next_inst_ptr":4}"#,
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn finds_divergence() {
+        let mut a = vec![];
+        let mut b = vec![];
+        countdown(3).run_traced(&mut a).unwrap();
+        countdown(3).run_traced(&mut b).unwrap();
+        assert_eq!(None, first_divergence(&a, &b));
+
+        let mut c = vec![];
+        countdown(4).run_traced(&mut c).unwrap();
+        assert_eq!(Some(0), first_divergence(&a, &c));
+        assert_eq!(Some(1), first_divergence(&a[..1], &a));
+    }
+}
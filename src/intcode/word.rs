@@ -0,0 +1,200 @@
+//! Word types an Intcode machine can compute with.
+//!
+//! Plain `i64` wraps on overflow like the original puzzle machines. `Checked` is an `i64` that
+//! faults with `IntcodeError::Overflow` instead, and `BigInt` never overflows at all, at the cost
+//! of allocating for every large value. Addresses, opcodes and parameter modes still have to fit
+//! in an `i64` whatever the word type.
+
+use std::fmt;
+use std::str::FromStr;
+
+use num::{BigInt, ToPrimitive, Zero};
+
+pub trait Word: Clone + PartialEq + PartialOrd + fmt::Debug {
+    fn from_i64(val: i64) -> Self;
+
+    /// The value as an `i64`, or `None` if it doesn't fit.
+    fn to_i64(&self) -> Option<i64>;
+
+    /// `self + other`, or `None` if the result doesn't fit in the word.
+    fn try_add(&self, other: &Self) -> Option<Self>;
+
+    /// `self * other`, or `None` if the result doesn't fit in the word.
+    fn try_mul(&self, other: &Self) -> Option<Self>;
+
+    fn zero() -> Self {
+        Self::from_i64(0)
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == Self::zero()
+    }
+}
+
+/// Wraps on overflow.
+impl Word for i64 {
+    fn from_i64(val: i64) -> Self {
+        val
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        Some(*self)
+    }
+
+    fn try_add(&self, other: &Self) -> Option<Self> {
+        Some(self.wrapping_add(*other))
+    }
+
+    fn try_mul(&self, other: &Self) -> Option<Self> {
+        Some(self.wrapping_mul(*other))
+    }
+}
+
+/// An `i64` word whose arithmetic faults on overflow instead of wrapping.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Checked(pub i64);
+
+impl Word for Checked {
+    fn from_i64(val: i64) -> Self {
+        Checked(val)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        Some(self.0)
+    }
+
+    fn try_add(&self, other: &Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Checked)
+    }
+
+    fn try_mul(&self, other: &Self) -> Option<Self> {
+        self.0.checked_mul(other.0).map(Checked)
+    }
+}
+
+impl fmt::Display for Checked {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for Checked {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Checked)
+    }
+}
+
+/// Never overflows.
+impl Word for BigInt {
+    fn from_i64(val: i64) -> Self {
+        BigInt::from(val)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        ToPrimitive::to_i64(self)
+    }
+
+    fn try_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn try_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn is_zero(&self) -> bool {
+        Zero::is_zero(self)
+    }
+}
+
+/// Converts an `i64` program, e.g. one from `asm::assemble`, to another word type.
+pub fn widen<W: Word>(program: &[i64]) -> Vec<W> {
+    program.iter().map(|val| W::from_i64(*val)).collect()
+}
+
+/// Parses a comma separated program into any word type, so literals too large for an `i64` can
+/// be loaded straight into a `BigInt` machine.
+pub fn parse_words<W: FromStr>(raw: &str) -> Result<Vec<W>, W::Err> {
+    raw.trim()
+        .split(',')
+        .map(|part| part.trim().parse())
+        .collect()
+}
+
+/// `val` as an `i64`, saturating at the bounds, for reporting in errors.
+pub(crate) fn saturate<W: Word>(val: &W) -> i64 {
+    match val.to_i64() {
+        Some(val) => val,
+        None if *val < W::zero() => i64::MIN,
+        None => i64::MAX,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+    use crate::intcode::{Computer, IntcodeError};
+
+    const FACTORIAL: &str = "
+            in [n]
+            add 1, 0, [acc]
+    loop:   mul [acc], [n], [acc]
+            add [n], -1, [n]
+            jt [n], loop
+            out [acc]
+            hlt
+    n:      data 0
+    acc:    data 0
+    ";
+
+    fn factorial<W: Word>(n: i64) -> crate::intcode::Result<Vec<W>> {
+        let mut computer = Computer::new(widen::<W>(&assemble(FACTORIAL).unwrap()));
+        computer.push_input(W::from_i64(n));
+        computer.run_to_halt()
+    }
+
+    #[test]
+    fn big_factorials() {
+        let expected: BigInt = "30414093201713378043612608166064768844377641568960512000000000000"
+            .parse()
+            .unwrap();
+        assert_eq!(vec![expected], factorial::<BigInt>(50).unwrap());
+        assert_eq!(vec![2432902008176640000], factorial::<i64>(20).unwrap());
+    }
+
+    #[test]
+    fn checked_words_fault_on_overflow() {
+        assert_eq!(
+            vec![Checked(2432902008176640000)],
+            factorial::<Checked>(20).unwrap()
+        );
+
+        match factorial::<Checked>(21) {
+            Err(IntcodeError::Overflow { opcode: 2, .. }) => {}
+            other => panic!("expected an overflow, got {:?}", other),
+        }
+
+        // Plain words wrap, as the puzzles expect.
+        assert_eq!(
+            vec![2432902008176640000i64.wrapping_mul(21)],
+            factorial::<i64>(21).unwrap()
+        );
+    }
+
+    #[test]
+    fn huge_literals() {
+        let program: Vec<BigInt> = parse_words("104,100000000000000000000,99").unwrap();
+        let outputs = Computer::new(program).run_to_halt().unwrap();
+        assert_eq!("100000000000000000000", outputs[0].to_string());
+
+        let mut computer =
+            Computer::new(parse_words::<BigInt>("4,100000000000000000000,99").unwrap());
+        assert!(matches!(
+            computer.run_to_halt(),
+            Err(IntcodeError::AddressOutOfRange { .. })
+        ));
+    }
+}
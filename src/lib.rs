@@ -12,4 +12,5 @@ pub mod day6;
 pub mod day7;
 pub mod day8;
 pub mod day9;
+pub mod intcode;
 pub mod util;